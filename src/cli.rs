@@ -1,8 +1,13 @@
-use crate::utils::version;
+use crate::utils::{config::Config, dry_run, error::ConfigError, paths, version};
 use anyhow::Result;
 use clap::Parser;
 use colored::Colorize;
 use semver::Version;
+use std::{
+    collections::BTreeMap,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
 
 use crate::command;
 
@@ -11,22 +16,82 @@ const LATEST_RELEASE_URL: &str = "https://github.com/MichaelMandel26/beam/releas
 #[derive(Parser, Debug)]
 #[clap(name = "beam", about = "Easier connection to teleport hosts", version)]
 pub struct Beam {
-    #[clap(short, long, help = "The profile to use")]
+    #[clap(short, long, help = "The profile to use [env: BEAM_PROFILE]")]
     pub profile: Option<String>,
 
     #[clap(
         short,
         long,
-        help = "The user which will be used to connect to the host. (default is the current system user)"
+        help = "The user which will be used to connect to the host. (default is the current system user) [env: BEAM_USER]"
     )]
     pub user: Option<String>,
 
-    #[clap(long, help = "The proxy to use")]
+    #[clap(long, help = "The proxy to use [env: BEAM_PROXY]")]
     pub proxy: Option<String>,
 
-    #[clap(long, help = "The auth to use")]
+    #[clap(long, help = "The auth to use [env: BEAM_AUTH]")]
     pub auth: Option<String>,
 
+    #[clap(long, help = "The trusted (leaf) cluster to use [env: BEAM_CLUSTER]")]
+    pub cluster: Option<String>,
+
+    #[clap(
+        long = "all-clusters",
+        conflicts_with = "cluster",
+        help = "Show the nodes of every reachable cluster in the picker"
+    )]
+    pub all_clusters: bool,
+
+    #[clap(
+        global = true,
+        long = "config-dir",
+        help = "The directory holding the profiles and the cache [env: BEAM_CONFIG_DIR]"
+    )]
+    pub config_dir: Option<PathBuf>,
+
+    #[clap(
+        global = true,
+        long = "non-interactive",
+        help = "Never prompt for input and fail on missing configuration instead. (default if stdin is not a terminal)"
+    )]
+    pub non_interactive: bool,
+
+    #[clap(
+        global = true,
+        long = "choose-login",
+        help = "Choose one of the logins allowed by your teleport roles, instead of the configured one"
+    )]
+    pub choose_login: bool,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["proxy", "all_clusters"],
+        help = "Show the nodes of the proxies of all profiles in the picker"
+    )]
+    pub all: bool,
+
+    #[clap(
+        global = true,
+        long,
+        help = "A teleport predicate the listed nodes must match, e.g. 'labels[\"env\"] == \"prod\"'"
+    )]
+    pub query: Option<String>,
+
+    #[clap(
+        global = true,
+        long,
+        help = "Keywords the hostname or labels of the listed nodes must contain, separated by commas"
+    )]
+    pub search: Option<String>,
+
+    #[clap(
+        global = true,
+        long,
+        value_parser = parse_labels,
+        help = "Labels the listed nodes must have, e.g. env=prod,team=payments"
+    )]
+    pub labels: Option<BTreeMap<String, String>>,
+
     #[clap(short, long = "clear-cache", help = "Whether to clear the cache")]
     pub clear_cache: bool,
 
@@ -36,9 +101,17 @@ pub struct Beam {
     #[clap(
         global = true,
         long = "tsh",
-        help = "output the tsh command, that would be used to connect to the node"
+        help = "Print the commands beam would run, e.g. to connect to the node, instead of running them"
     )]
     pub tsh: bool,
+
+    #[clap(
+        global = true,
+        long = "tsh-format",
+        value_enum,
+        help = "How to print the commands, implies --tsh. (default is shell)"
+    )]
+    pub tsh_format: Option<dry_run::Format>,
 }
 
 #[derive(Parser, Debug)]
@@ -51,32 +124,88 @@ pub enum Command {
     Configure(command::configure::Configure),
     Login(command::login::Login),
     Logout(command::logout::Logout),
+    /// Requesting access to a role or host through a teleport access request
+    Request(command::request::Request),
+    /// Listing the root and leaf clusters reachable through the proxy
+    Clusters(command::clusters::Clusters),
+    /// Checking profiles, the teleport cli and beam's directories for problems
+    Doctor(command::doctor::Doctor),
+    /// Connecting to a database proxied by teleport or starting a local tunnel to it
+    Db(command::db::Db),
+    /// Logging in to a kubernetes cluster through teleport
+    Kube(command::kube::Kube),
+    /// Logging in to an application behind teleport or starting a local proxy for it
+    App(command::app::App),
+    /// Printing OpenSSH Host entries, which connect to teleport hosts through tsh
+    SshConfig(command::ssh_config::SshConfig),
 }
 
 impl Beam {
     pub async fn run(&self) -> Result<()> {
-        Beam::check_for_dot_beam_dir()?;
+        if let Some(config_dir) = &self.config_dir {
+            paths::set_config_dir(config_dir.to_owned());
+        }
+        Beam::check_for_config_dir()?;
         // Asynchronously getting the latest version from GitHub
         let latest_version =
             tokio::spawn(async move { version::get_latest_release(LATEST_RELEASE_URL).await });
 
-        self.execute_command()?;
+        self.execute_command().await?;
 
         // Printing notification if the latest version is newer than the current version
         Beam::check_for_update(latest_version.await?)?;
         Ok(())
     }
 
-    pub fn execute_command(&self) -> Result<()> {
+    pub async fn execute_command(&self) -> Result<()> {
         match &self.cmd {
             Some(Command::Connect(command)) => command.run(self),
-            Some(Command::Profile(command)) => command.run(),
+            Some(Command::Profile(command)) => command.run(self),
             Some(Command::List(command)) => command.run(self),
             Some(Command::Login(command)) => command.run(self),
-            Some(Command::Logout(command)) => command.run(),
+            Some(Command::Logout(command)) => command.run(self),
+            Some(Command::Request(command)) => command.run(self),
+            Some(Command::Clusters(command)) => command.run(self),
+            Some(Command::Doctor(command)) => command.run(self),
+            Some(Command::Db(command)) => command.run(self),
+            Some(Command::Kube(command)) => command.run(self),
+            Some(Command::App(command)) => command.run(self),
+            Some(Command::SshConfig(command)) => command.run(self),
             Some(Command::Completions(command)) => command.run(),
-            Some(Command::Configure(command)) => command.run(),
-            None => command::default::Default::run(self),
+            Some(Command::Configure(command)) => command.run(self),
+            None => command::default::Default::run(self).await,
+        }
+    }
+
+    /// The filters given through flags, which override those of the profile
+    pub fn filter_overrides(&self) -> Config {
+        Config {
+            query: self.query.clone(),
+            search: self.search.clone(),
+            label_filter: self.labels.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// The format to print the commands in, when beam should not run them
+    pub fn dry_run(&self) -> Option<dry_run::Format> {
+        match (self.tsh, self.tsh_format) {
+            (_, Some(format)) => Some(format),
+            (true, None) => Some(dry_run::Format::default()),
+            (false, None) => None,
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        !self.non_interactive && std::io::stdin().is_terminal()
+    }
+
+    /// Fails with a [`ConfigError::InteractionRequired`], when beam is not allowed to prompt
+    pub fn ensure_interactive(&self, action: &str) -> Result<()> {
+        if self.is_interactive() {
+            Ok(())
+        } else {
+            Err(ConfigError::InteractionRequired(action.to_string()).into())
         }
     }
 
@@ -95,23 +224,55 @@ impl Beam {
         Ok(())
     }
 
-    pub fn check_for_dot_beam_dir() -> Result<()> {
-        let home_dir = home::home_dir().expect("Could not find home directory");
-        let dot_beam_dir = home_dir.join(".beam");
+    pub fn check_for_config_dir() -> Result<()> {
+        paths::migrate_legacy_dir()?;
+        Beam::create_config_dir(&paths::config_dir())
+    }
 
-        if !dot_beam_dir.exists() {
-            std::fs::create_dir(&dot_beam_dir)?;
+    fn create_config_dir(config_dir: &Path) -> Result<()> {
+        if !config_dir.exists() {
+            std::fs::create_dir_all(config_dir)?;
         }
 
         Ok(())
     }
 }
 
+/// Parses labels like `env=prod,team=payments`
+pub(crate) fn parse_labels(labels: &str) -> Result<BTreeMap<String, String>, String> {
+    labels
+        .split(',')
+        .filter(|label| !label.trim().is_empty())
+        .map(|label| match label.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!("{} is not a label like key=value", label)),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
-    fn test_check_for_dot_beam_dir() {
-        assert!(Beam::check_for_dot_beam_dir().is_ok());
+    fn test_create_config_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_dir = temp_dir.path().join("beam");
+        assert!(Beam::create_config_dir(&config_dir).is_ok());
+        assert!(config_dir.is_dir());
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        assert_eq!(
+            parse_labels("env=prod, team=payments").unwrap(),
+            BTreeMap::from([
+                ("env".to_owned(), "prod".to_owned()),
+                ("team".to_owned(), "payments".to_owned())
+            ])
+        );
+        assert!(parse_labels("env").is_err());
+        assert!(parse_labels("=prod").is_err());
     }
 }
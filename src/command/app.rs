@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+use crate::teleport::app::App as TeleportApp;
+use crate::teleport::cli;
+use crate::teleport::resource::{self, Chosen, Resource};
+use crate::utils::dry_run;
+
+#[derive(Debug, Parser)]
+pub struct App {
+    #[clap(help = "The app to log in to, instead of choosing one in the picker")]
+    name: Option<String>,
+
+    #[clap(
+        long,
+        help = "Start a local proxy for the app on this port, instead of printing its URL and certificates"
+    )]
+    port: Option<u16>,
+}
+
+impl App {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        let Chosen {
+            resource: app,
+            settings,
+            proxy,
+            listing,
+        } = match resource::choose::<TeleportApp>(beam, self.name.as_deref())? {
+            Some(chosen) => chosen,
+            None => return Ok(()),
+        };
+
+        let mut args = vec![format!("--proxy={}", proxy)];
+        if let Some(cluster) = listing.cluster() {
+            args.push(format!("--cluster={}", cluster));
+        }
+
+        let mut login_args = vec!["apps".to_string(), "login".to_string()];
+        login_args.extend(args.iter().cloned());
+        login_args.push(app.name().to_string());
+
+        // Without a local proxy, tsh prints the URL and the paths of the certificates to use
+        let mut action_args = match self.port.or(settings.profile.value.config.app_port) {
+            Some(port) => vec![
+                "proxy".to_string(),
+                "app".to_string(),
+                format!("--port={}", port),
+            ],
+            None => vec!["apps".to_string(), "config".to_string()],
+        };
+        action_args.extend(args);
+        action_args.push(app.name().to_string());
+
+        if let Some(format) = beam.dry_run() {
+            return dry_run::print(
+                format,
+                &[
+                    dry_run::Command::tsh(Some(&proxy), &login_args),
+                    dry_run::Command::tsh(Some(&proxy), &action_args),
+                ],
+            );
+        }
+
+        if !cli::run(&proxy, &login_args)? {
+            return Err(anyhow!("Login to app {} failed", app.name()));
+        }
+        if !cli::run(&proxy, &action_args)? {
+            return Err(anyhow!(
+                "tsh {} for app {} failed",
+                action_args[..2].join(" "),
+                app.name()
+            ));
+        }
+
+        Ok(())
+    }
+}
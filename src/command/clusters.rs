@@ -0,0 +1,53 @@
+use anyhow::Result;
+use clap::Parser;
+use colored::Colorize;
+
+use crate::teleport::{cli, cluster};
+use crate::utils::dry_run;
+use crate::utils::settings::Settings;
+
+#[derive(Debug, Parser)]
+pub struct Clusters {}
+
+impl Clusters {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        let settings = Settings::get(beam)?;
+        let proxy = settings.proxy()?;
+
+        if let Some(format) = beam.dry_run() {
+            let args = cli::clusters_args(proxy);
+            return dry_run::print(format, &[dry_run::Command::tsh(Some(proxy), &args)]);
+        }
+
+        cli::ensure_logged_in(proxy, settings.auth(), settings.teleport_user().as_deref())?;
+
+        let clusters = cluster::get(proxy)?;
+        let width = clusters
+            .iter()
+            .map(|cluster| cluster.name.len())
+            .max()
+            .unwrap_or(0);
+        for cluster in clusters {
+            let status = if cluster.is_online() {
+                cluster.status.green()
+            } else {
+                cluster.status.red()
+            };
+            // Marks the cluster beam uses, which is tsh's selected cluster without a configured one
+            let is_used = match settings.cluster() {
+                Some(name) => name == cluster.name,
+                None => cluster.selected,
+            };
+            let marker = if is_used { "*" } else { " " };
+            println!(
+                "{} {:<width$} {:<6} {}",
+                marker,
+                cluster.name,
+                cluster.kind,
+                status,
+                width = width + 2
+            );
+        }
+        Ok(())
+    }
+}
@@ -47,6 +47,7 @@ impl Default {
             std::cmp::Ordering::Less => Profile::new_interactive(true)?,
         };
 
+        profile.ensure_writable()?;
         Profile::wizard(&mut profile)?;
 
         Profiles::write(profile)?;
@@ -17,7 +17,8 @@ pub enum Command {
 }
 
 impl Configure {
-    pub fn run(&self) -> Result<()> {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        beam.ensure_interactive("beam configure")?;
         match &self.command {
             Some(Command::Profile(cmd)) => cmd.run(),
             None => default::Default::run(),
@@ -2,7 +2,7 @@ use anyhow::Result;
 use clap::Parser;
 
 use crate::utils::profile;
-use crate::utils::profiles::{Profiles, DEFAULT_PROFILE};
+use crate::utils::profiles::Profiles;
 
 #[derive(Parser, Debug)]
 pub struct Profile {
@@ -13,10 +13,11 @@ pub struct Profile {
 impl Profile {
     pub fn run(&self) -> Result<()> {
         let mut profile = match self.profile.as_ref() {
-            "default" => DEFAULT_PROFILE.clone(),
+            "default" => Profiles::resolve_default(true)?,
             "new" => profile::Profile::new_interactive(false)?,
             _ => profile::Profile::get(&self.profile)?,
         };
+        profile.ensure_writable()?;
         profile::Profile::wizard(&mut profile)?;
         Profiles::write(profile)?;
         Ok(())
@@ -1,10 +1,11 @@
-use anyhow::{ensure, Context, Result};
+use anyhow::{Context, Result};
 use clap::Parser;
+use std::collections::HashMap;
 
 use crate::ssh;
+use crate::teleport::node::Node;
 use crate::teleport::{cli, node};
-use crate::utils::profile::Profile;
-use crate::utils::profiles::{Profiles, DEFAULT_PROFILE};
+use crate::utils::settings::Settings;
 
 #[derive(Debug, Parser)]
 pub struct Connect {
@@ -13,61 +14,60 @@ pub struct Connect {
 }
 
 impl Connect {
-    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
-        let profile = match &beam.profile.is_some() {
-            true => Profile::get(beam.profile.as_ref().unwrap().as_str())?,
-            false => {
-                let profiles = Profiles::get()?;
-                match Profiles::get_matching(&self.host, profiles)? {
-                    Some(p) => p,
-                    None => DEFAULT_PROFILE.clone(),
-                }
-            }
-        };
-
-        let proxy = match &beam.proxy {
-            Some(proxy) => proxy,
-            None => profile.config.proxy.as_ref().context("No proxy configured to login with. Please use --proxy or configure it using beam configure")?
-        };
+    pub fn new(host: &str) -> Connect {
+        Connect {
+            host: host.to_string(),
+        }
+    }
 
-        let fallback = whoami::username();
-        let user = match &beam.user {
-            Some(user) => user,
-            None => profile.config.username.as_ref().context("No username configured to login with. Please use --username or configure it using beam configure").unwrap_or(&fallback)
-        };
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        self.connect(beam, !beam.clear_cache)
+    }
 
-        let auth = match &beam.auth {
-            Some(auth) => Some(auth),
-            None => profile.config.auth.as_ref(),
-        };
+    /// Connects without using the cached nodes, e.g. after assuming another role
+    pub fn run_uncached(&self, beam: &crate::cli::Beam) -> Result<()> {
+        self.connect(beam, false)
+    }
 
-        if !cli::is_logged_in()? || !cli::cmp_logged_in_proxy_with(proxy)? {
-            let exit_status = cli::login(proxy, auth, user)?;
-            if !exit_status.success() {
-                return Err(anyhow::anyhow!("Login failed"));
-            }
-        }
+    fn connect(&self, beam: &crate::cli::Beam, use_cache: bool) -> Result<()> {
+        // The labels of the node are not known before listing the nodes, so the proxy
+        // is taken from a profile matching the hostname only
+        let host_settings = Settings::get_for_node(beam, &Node::new(&self.host, HashMap::new()))?;
+        let proxy = host_settings.proxy()?;
 
-        let nodes = node::get(!beam.clear_cache, proxy)?;
-        ensure!(
-            nodes.iter().any(|node| node.spec.hostname == self.host),
-            "Host not found in teleport"
-        );
+        cli::ensure_logged_in(
+            proxy,
+            host_settings.auth(),
+            host_settings.teleport_user().as_deref(),
+        )?;
 
-        let fallback = whoami::username();
-        let username = match &beam.user {
-            Some(username) => username,
-            None => profile.config.username.as_ref().context("No username configured to login with. Please use --username or configure it using beam configure").unwrap_or(&fallback)
-        };
+        let nodes = node::get(
+            use_cache,
+            proxy,
+            &host_settings.listing(),
+            host_settings.cache_ttl.value,
+        )?;
+        let node = nodes
+            .iter()
+            .find(|node| node.spec.hostname == self.host)
+            .context("Host not found in teleport")?;
 
-        let tsh_args = ssh::connect::get_tsh_command(&self.host, username, &profile)?;
-        if beam.tsh {
-            println!("{}", tsh_args.join(" "));
-            return Ok(());
+        let settings = Settings::get_for_node(beam, node)?;
+        let login = ssh::login::check(beam, proxy, settings.login(node)?, beam.choose_login)?;
+        let session = ssh::connect::Session::new(
+            &self.host,
+            &login.value,
+            proxy,
+            host_settings.cluster(),
+            &settings.profile.value,
+        )?;
+        if let Some(format) = beam.dry_run() {
+            eprintln!("# login {} from {}", login.value, login.source);
+            return session.dry_run(format);
         }
 
         clearscreen::clear()?;
-        ssh::connect::connect(tsh_args)?;
+        ssh::connect::connect(proxy, &session.ssh_args())?;
 
         Ok(())
     }
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+use crate::teleport::cli;
+use crate::teleport::database::Database;
+use crate::teleport::resource::{self, Chosen, Resource};
+use crate::utils::dry_run;
+
+#[derive(Debug, Parser)]
+pub struct Db {
+    #[clap(help = "The database to connect to, instead of choosing one in the picker")]
+    name: Option<String>,
+
+    #[clap(long = "db-user", help = "The database user to log in as")]
+    db_user: Option<String>,
+
+    #[clap(long = "db-name", help = "The name of the database to connect to")]
+    db_name: Option<String>,
+
+    #[clap(
+        long,
+        help = "Start a local tunnel to the database, instead of launching the database client"
+    )]
+    tunnel: bool,
+
+    #[clap(long, help = "The local port of the tunnel, implies --tunnel")]
+    port: Option<u16>,
+}
+
+impl Db {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        let Chosen {
+            resource: database,
+            settings,
+            proxy,
+            listing,
+        } = match resource::choose::<Database>(beam, self.name.as_deref())? {
+            Some(chosen) => chosen,
+            None => return Ok(()),
+        };
+        let config = &settings.profile.value.config;
+
+        let mut args = vec![format!("--proxy={}", proxy)];
+        if let Some(cluster) = listing.cluster() {
+            args.push(format!("--cluster={}", cluster));
+        }
+        if let Some(db_user) = self.db_user.as_ref().or(config.db_user.as_ref()) {
+            args.push(format!("--db-user={}", db_user));
+        }
+        if let Some(db_name) = self.db_name.as_ref().or(config.db_name.as_ref()) {
+            args.push(format!("--db-name={}", db_name));
+        }
+
+        let name = database.name().to_string();
+        let mut login_args = vec!["db".to_string(), "login".to_string()];
+        login_args.extend(args.iter().cloned());
+        login_args.push(name.clone());
+
+        let port = self.port.or(config.db_tunnel_port);
+        let mut action_args = if self.tunnel || port.is_some() {
+            let mut tunnel_args = vec![
+                "proxy".to_string(),
+                "db".to_string(),
+                "--tunnel".to_string(),
+            ];
+            if let Some(port) = port {
+                tunnel_args.push(format!("--port={}", port));
+            }
+            tunnel_args
+        } else {
+            vec!["db".to_string(), "connect".to_string()]
+        };
+        action_args.extend(args);
+        action_args.push(name);
+
+        if let Some(format) = beam.dry_run() {
+            return dry_run::print(
+                format,
+                &[
+                    dry_run::Command::tsh(Some(&proxy), &login_args),
+                    dry_run::Command::tsh(Some(&proxy), &action_args),
+                ],
+            );
+        }
+
+        if !cli::run(&proxy, &login_args)? {
+            return Err(anyhow!("Login to database {} failed", database.name()));
+        }
+        if !cli::run(&proxy, &action_args)? {
+            return Err(anyhow!(
+                "tsh {} for database {} failed",
+                action_args[..2].join(" "),
+                database.name()
+            ));
+        }
+
+        Ok(())
+    }
+}
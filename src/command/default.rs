@@ -1,88 +1,162 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Result};
 use clap::Parser;
 
 use crate::ssh;
-use crate::teleport::node::SkimString;
+use crate::teleport::node::{Node, SkimString};
 use crate::teleport::{cli, node};
-use crate::utils::profile::Profile;
-use crate::utils::profiles::{Profiles, DEFAULT_PROFILE};
+use crate::utils::profiles::Profiles;
+use crate::utils::project::Project;
+use crate::utils::settings::{Setting, Settings, Source};
 use crate::utils::skim;
 
 #[derive(Debug, Parser)]
 pub struct Default {}
 
 impl Default {
-    pub fn run(beam: &crate::cli::Beam) -> Result<()> {
-        let profile = match &beam.profile.is_some() {
-            true => Profile::get(beam.profile.as_ref().unwrap().as_str())?,
-            false => DEFAULT_PROFILE.clone(),
-        };
+    pub async fn run(beam: &crate::cli::Beam) -> Result<()> {
+        beam.ensure_interactive("Selecting a host")?;
+        if beam.all {
+            return Default::run_all(beam).await;
+        }
 
-        let proxy = match &beam.proxy {
-            Some(proxy) => proxy,
-            None => profile.config.proxy.as_ref().context("No proxy configured to login with. Please use --proxy or configure it using beam configure")?
-        };
+        let settings = Settings::get(beam)?;
+        // The proxy stays the same, even if the selected node matches a profile with another proxy
+        let proxy = &settings.proxy()?.to_owned();
 
-        let fallback = whoami::username();
-        let user = match &beam.user {
-            Some(user) => user,
-            None => profile.config.username.as_ref().context("No username configured to login with. Please use --username or configure it using beam configure").unwrap_or(&fallback)
-        };
+        cli::ensure_logged_in(proxy, settings.auth(), settings.teleport_user().as_deref())?;
 
-        let auth = match &beam.auth {
-            Some(auth) => Some(auth),
-            None => profile.config.auth.as_ref(),
+        let profile = &settings.profile.value;
+        let mut nodes = if beam.all_clusters {
+            node::get_all_clusters(
+                !beam.clear_cache,
+                proxy,
+                &settings.listing(),
+                settings.cache_ttl.value,
+            )?
+        } else {
+            node::get(
+                !beam.clear_cache,
+                proxy,
+                &settings.listing(),
+                settings.cache_ttl.value,
+            )?
         };
-
-        if !cli::is_logged_in()? || !cli::cmp_logged_in_proxy_with(proxy)? {
-            let exit_status = cli::login(proxy, auth, user)?;
-            if !exit_status.success() {
-                return Err(anyhow::anyhow!("Login failed"));
-            }
+        if let Some(label_filter) = &profile.config.label_filter {
+            nodes.retain(|node| node.has_labels(label_filter));
         }
 
-        let nodes = node::get(!beam.clear_cache, proxy)?;
-
         let label_whitelist = profile.config.label_whitelist.clone();
 
-        let items = nodes.to_skim_string(label_whitelist);
+        let items = nodes.clone().to_skim_string(label_whitelist);
 
-        let selected_item = match skim::skim(items)? {
-            Some(item) => item,
+        let selection = match skim::skim(items, &[ssh::login::CHOOSE_LOGIN_KEY])? {
+            Some(selection) => selection,
             None => {
                 return Ok(());
             }
         };
 
-        let host = selected_item.split(' ').next().unwrap();
-        let profiles = Profiles::get()?;
-        let matched_profile = Profiles::get_matching(host, profiles)?;
-
-        match matched_profile {
-            Some(matched_profile) => {
-                let tsh_args = ssh::connect::get_tsh_command(
-                    host,
-                    matched_profile.config.username.as_ref().unwrap(),
-                    &matched_profile,
-                )?;
-                if beam.tsh {
-                    println!("{}", tsh_args.join(" "));
-                    return Ok(());
-                }
-                clearscreen::clear()?;
-                ssh::connect::connect(tsh_args)?
-            }
+        // With all clusters, the cluster column follows the hostname
+        let mut columns = selection.item.split_whitespace();
+        let host = columns.next().unwrap();
+        let cluster = match beam.all_clusters {
+            true => columns.next(),
+            false => settings.cluster(),
+        };
+        let node = nodes
+            .iter()
+            .find(|node| node.spec.hostname == host && node.cluster.as_deref() == cluster)
+            .unwrap();
+
+        // An explicitly selected profile is kept, otherwise a profile matching the node is used
+        let settings = match settings.profile.source {
+            Source::Flag | Source::Env(_) => settings,
+            _ => Settings::get_for_node(beam, node)?,
+        };
+
+        let choose_login =
+            beam.choose_login || selection.key.as_deref() == Some(ssh::login::CHOOSE_LOGIN_KEY);
+        Default::connect(beam, &settings, proxy, node, choose_login)
+    }
+
+    /// Shows the nodes of the proxies of all profiles and connects with the profile the selected node was listed with
+    async fn run_all(beam: &crate::cli::Beam) -> Result<()> {
+        let mut profiles = Profiles::by_proxy(Profiles::get()?);
+        for profile in profiles.iter_mut() {
+            profile.config.merge(&beam.filter_overrides());
+        }
+        if profiles.is_empty() {
+            return Err(anyhow!("None of your profiles has a proxy configured"));
+        }
+
+        let mut nodes = node::get_all_proxies(!beam.clear_cache, profiles.clone()).await?;
+        nodes.retain(|node| {
+            profiles
+                .iter()
+                .find(|profile| node.profile.as_ref() == Some(&profile.name))
+                .and_then(|profile| profile.config.label_filter.as_ref())
+                .is_none_or(|label_filter| node.has_labels(label_filter))
+        });
+
+        let items = nodes.clone().to_skim_string(None);
+        let selection = match skim::skim(items, &[ssh::login::CHOOSE_LOGIN_KEY])? {
+            Some(selection) => selection,
             None => {
-                let tsh_args = ssh::connect::get_tsh_command(host, user, &profile)?;
-                if beam.tsh {
-                    println!("{}", tsh_args.join(" "));
-                    return Ok(());
-                }
-                clearscreen::clear()?;
-                ssh::connect::connect(tsh_args)?
+                return Ok(());
             }
         };
 
+        // The profile and proxy columns follow the hostname
+        let mut columns = selection.item.split_whitespace();
+        let host = columns.next().unwrap();
+        let profile_name = columns.next();
+        let node = nodes
+            .iter()
+            .find(|node| node.spec.hostname == host && node.profile.as_deref() == profile_name)
+            .unwrap();
+        let profile = profiles
+            .into_iter()
+            .find(|profile| node.profile.as_ref() == Some(&profile.name))
+            .unwrap();
+        let proxy = node.proxy.clone().unwrap();
+
+        let settings = Settings::from_profile(
+            beam,
+            &Project::get()?,
+            Setting {
+                value: profile,
+                source: Source::Chosen,
+            },
+        )?;
+        cli::ensure_logged_in(&proxy, settings.auth(), settings.teleport_user().as_deref())?;
+
+        let choose_login =
+            beam.choose_login || selection.key.as_deref() == Some(ssh::login::CHOOSE_LOGIN_KEY);
+        Default::connect(beam, &settings, &proxy, node, choose_login)
+    }
+
+    fn connect(
+        beam: &crate::cli::Beam,
+        settings: &Settings,
+        proxy: &str,
+        node: &Node,
+        choose_login: bool,
+    ) -> Result<()> {
+        let login = ssh::login::check(beam, proxy, settings.login(node)?, choose_login)?;
+        let session = ssh::connect::Session::new(
+            &node.spec.hostname,
+            &login.value,
+            proxy,
+            node.cluster.as_deref(),
+            &settings.profile.value,
+        )?;
+        if let Some(format) = beam.dry_run() {
+            eprintln!("# login {} from {}", login.value, login.source);
+            return session.dry_run(format);
+        }
+        clearscreen::clear()?;
+        ssh::connect::connect(proxy, &session.ssh_args())?;
+
         Ok(())
     }
 }
@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use colored::Colorize;
+use std::fs;
+
+use crate::teleport::backend::{self, TshCommand};
+use crate::teleport::cli;
+use crate::utils::{dry_run, paths, profile::Profile, profiles::Profiles};
+
+#[derive(Debug, Parser)]
+pub struct Doctor {}
+
+impl Doctor {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        if let Some(format) = beam.dry_run() {
+            let commands: Vec<_> = tsh_commands(&Profiles::load()?)
+                .iter()
+                .map(|tsh| dry_run::Command {
+                    argv: tsh.command_line("version", &[]),
+                    is_tsh: true,
+                })
+                .collect();
+            return dry_run::print(format, &commands);
+        }
+
+        let mut problems = vec![];
+
+        let profiles = match Profiles::load() {
+            Ok(profiles) => {
+                let profile_problems = Profiles::validate(&profiles);
+                if profile_problems.is_empty() {
+                    ok(&format!("{} profile(s) are valid", profiles.len()));
+                }
+                problems.extend(profile_problems);
+                if let Err(err) = Profiles::verify_profiles_integrity(&profiles) {
+                    problems.push(err.to_string());
+                }
+                profiles
+            }
+            Err(err) => {
+                problems.push(format!("Could not load profiles: {:#}", err));
+                vec![]
+            }
+        };
+
+        match Profiles::unknown_keys() {
+            Ok(keys) => problems.extend(keys.into_iter().map(|key| {
+                format!(
+                    "Unknown key {} in {} will be dropped on the next write",
+                    key,
+                    paths::profiles_file().display()
+                )
+            })),
+            Err(err) => problems.push(format!(
+                "Could not check profiles for unknown keys: {:#}",
+                err
+            )),
+        }
+
+        // Without loadable profiles, only the default tsh is checked
+        for tsh in tsh_commands(&profiles) {
+            let path = tsh.path.clone();
+            backend::set_tsh(None, tsh);
+            match cli::version(None) {
+                Ok(version) if path == "tsh" => ok(&format!("tsh {} is installed", version)),
+                Ok(version) => ok(&format!("tsh {} is installed at {}", version, path)),
+                Err(err) => problems.push(format!("{}: {}", path, err)),
+            }
+        }
+
+        let cache_dir = paths::cache_dir();
+        if cache_dir.exists() {
+            match check_cache_readable() {
+                Ok(_) => ok(&format!("Cache {} is readable", cache_dir.display())),
+                Err(err) => problems.push(format!(
+                    "Cache {} is not readable: {}",
+                    cache_dir.display(),
+                    err
+                )),
+            }
+        }
+
+        let config_dir = paths::config_dir();
+        match check_config_dir_writable() {
+            Ok(_) => ok(&format!(
+                "Config directory {} is writable",
+                config_dir.display()
+            )),
+            Err(err) => problems.push(format!(
+                "Config directory {} is not writable: {}",
+                config_dir.display(),
+                err
+            )),
+        }
+
+        for problem in &problems {
+            println!("{} {}", "✘".red(), problem);
+        }
+
+        match problems.len() {
+            0 => Ok(()),
+            count => Err(anyhow!("Found {} problem(s)", count)),
+        }
+    }
+}
+
+/// Every tsh used by one of the profiles, as they may differ in version
+fn tsh_commands(profiles: &[Profile]) -> Vec<TshCommand> {
+    let mut tsh_commands = vec![TshCommand::default()];
+    for profile in profiles {
+        let tsh = TshCommand::for_profile(profile);
+        if !tsh_commands.contains(&tsh) {
+            tsh_commands.push(tsh);
+        }
+    }
+    tsh_commands
+}
+
+fn ok(message: &str) {
+    println!("{} {}", "✔".green(), message);
+}
+
+fn check_cache_readable() -> Result<()> {
+    for entry in fs::read_dir(paths::cache_dir())? {
+        let path = entry?.path();
+        if path.is_file() {
+            fs::read(&path)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_config_dir_writable() -> Result<()> {
+    let config_dir = paths::config_dir();
+    fs::create_dir_all(&config_dir)?;
+    let probe = config_dir.join(".beam-doctor");
+    fs::write(&probe, "")?;
+    fs::remove_file(probe)?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::process::Command;
+
+use crate::teleport::cli;
+use crate::teleport::kube::KubeCluster;
+use crate::teleport::resource::{self, Chosen, Resource};
+use crate::utils::dry_run;
+
+#[derive(Debug, Parser)]
+pub struct Kube {
+    #[clap(help = "The kubernetes cluster to log in to, instead of choosing one in the picker")]
+    name: Option<String>,
+
+    #[clap(short, long, help = "The namespace to switch the kubectl context to")]
+    namespace: Option<String>,
+}
+
+impl Kube {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        let Chosen {
+            resource: kube_cluster,
+            settings,
+            proxy,
+            listing,
+        } = match resource::choose::<KubeCluster>(beam, self.name.as_deref())? {
+            Some(chosen) => chosen,
+            None => return Ok(()),
+        };
+
+        let mut login_args = vec![
+            "kube".to_string(),
+            "login".to_string(),
+            format!("--proxy={}", proxy),
+        ];
+        if let Some(cluster) = listing.cluster() {
+            login_args.push(format!("--cluster={}", cluster));
+        }
+        login_args.push(kube_cluster.name().to_string());
+
+        let namespace =
+            self.namespace
+                .as_ref()
+                .or(settings.profile.value.config.kube_namespace.as_ref());
+        // tsh kube login switches the current context, so only its namespace needs to be set
+        let namespace_args = namespace.map(|namespace| {
+            vec![
+                "config".to_string(),
+                "set-context".to_string(),
+                "--current".to_string(),
+                format!("--namespace={}", namespace),
+            ]
+        });
+
+        if let Some(format) = beam.dry_run() {
+            let mut commands = vec![dry_run::Command::tsh(Some(&proxy), &login_args)];
+            if let Some(namespace_args) = namespace_args {
+                commands.push(dry_run::Command::other(
+                    [&["kubectl".to_string()], namespace_args.as_slice()].concat(),
+                ));
+            }
+            return dry_run::print(format, &commands);
+        }
+
+        if !cli::run(&proxy, &login_args)? {
+            return Err(anyhow!(
+                "Login to kubernetes cluster {} failed",
+                kube_cluster.name()
+            ));
+        }
+        if let Some(namespace_args) = namespace_args {
+            let status = Command::new("kubectl")
+                .args(namespace_args)
+                .status()
+                .map_err(|_| anyhow!("Unable to run kubectl to switch the namespace"))?;
+            if !status.success() {
+                return Err(anyhow!("Switching the namespace failed"));
+            }
+        }
+
+        Ok(())
+    }
+}
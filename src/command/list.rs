@@ -1,9 +1,8 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::Parser;
 
-use crate::teleport::cli;
-use crate::utils::profile::Profile;
-use crate::utils::profiles::DEFAULT_PROFILE;
+use crate::teleport::{backend, cli};
+use crate::utils::{dry_run, settings::Settings, spinner};
 
 #[derive(Debug, Parser)]
 pub struct List {
@@ -13,34 +12,28 @@ pub struct List {
 
 impl List {
     pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
-        let profile = match &beam.profile.is_some() {
-            true => Profile::get(beam.profile.as_ref().unwrap().as_str())?,
-            false => DEFAULT_PROFILE.clone(),
-        };
-
-        let proxy = match &beam.proxy {
-            Some(proxy) => proxy,
-            None => profile.config.proxy.as_ref().context("No proxy configured to login with. Please use --proxy or configure it using beam configure")?
-        };
-
-        let fallback = whoami::username();
-        let user = match &beam.user {
-            Some(user) => user,
-            None => profile.config.username.as_ref().context("No username configured to login with. Please use --username or configure it using beam configure").unwrap_or(&fallback)
-        };
-
-        let auth = match &beam.auth {
-            Some(auth) => Some(auth),
-            None => profile.config.auth.as_ref(),
-        };
-
-        if !cli::is_logged_in()? || !cli::cmp_logged_in_proxy_with(proxy)? {
-            let exit_status = cli::login(proxy, auth, user)?;
-            if !exit_status.success() {
-                return Err(anyhow::anyhow!("Login failed"));
-            }
+        let settings = Settings::get(beam)?;
+        let proxy = settings.proxy()?;
+
+        if let Some(format) = beam.dry_run() {
+            let listing = settings.listing();
+            let ls_format = self.format.as_deref().unwrap_or(cli::LS_FORMAT);
+            let args = backend::ls_args(proxy, listing.cluster(), &listing.filter, ls_format);
+            return dry_run::print(format, &[dry_run::Command::tsh(Some(proxy), &args)]);
         }
-        let ls_output = cli::ls(self.format.as_ref())?;
+
+        cli::ensure_logged_in(proxy, settings.auth(), settings.teleport_user().as_deref())?;
+        let spinner = spinner::get_spinner();
+        spinner.set_message("Getting nodes from teleport...");
+        let listing = settings.listing();
+        let ls_output = cli::ls(
+            self.format.as_ref(),
+            proxy,
+            listing.cluster(),
+            &listing.filter,
+        );
+        spinner.finish_and_clear();
+        let ls_output = ls_output?;
 
         println!("{}", ls_output);
         Ok(())
@@ -1,44 +1,44 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use clap::Parser;
 use colored::Colorize;
 
-use crate::teleport::cli;
-use crate::utils::profile::Profile;
-use crate::utils::profiles::DEFAULT_PROFILE;
+use crate::teleport::{backend, cli};
+use crate::utils::dry_run;
+use crate::utils::settings::Settings;
 
 #[derive(Debug, Parser)]
 pub struct Login {}
 
 impl Login {
     pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
-        let profile = match &beam.profile.is_some() {
-            true => Profile::get(beam.profile.as_ref().unwrap().as_str())?,
-            false => DEFAULT_PROFILE.clone(),
-        };
-
-        let proxy = match &beam.proxy {
-            Some(proxy) => proxy,
-            None => profile.config.proxy.as_ref().context("No proxy configured to login with. Please use --proxy or configure it using beam configure")?
-        };
-
-        let fallback = whoami::username();
-        let user = match &beam.user {
-            Some(user) => user,
-            None => profile.config.username.as_ref().context("No username configured to login with. Please use --username or configure it using beam configure").unwrap_or(&fallback)
-        };
-
-        let auth = match &beam.auth {
-            Some(auth) => Some(auth),
-            None => profile.config.auth.as_ref(),
+        let settings = Settings::get(beam)?;
+        let proxy = settings.proxy()?;
+
+        // An existing login is switched to, without authenticating again
+        let (options, is_switch) = match cli::valid_profile(proxy)? {
+            Some(profile) if profile.active => {
+                println!("You are already logged in with {}", proxy.green());
+                return Ok(());
+            }
+            Some(_) => (cli::switch_options(proxy), true),
+            None => (
+                cli::login_options(proxy, settings.auth(), settings.teleport_user().as_deref()),
+                false,
+            ),
         };
+        if let Some(format) = beam.dry_run() {
+            return dry_run::print(
+                format,
+                &[dry_run::Command::tsh(Some(proxy), &options.args())],
+            );
+        }
 
-        if !cli::is_logged_in()? || !cli::cmp_logged_in_proxy_with(proxy)? {
-            let exit_status = cli::login(proxy, auth, user)?;
-            if !exit_status.success() {
-                return Err(anyhow::anyhow!("Login failed"));
-            }
-        } else {
-            println!("You are already logged in with {}", proxy.green());
+        let success = backend::get().login(&options)?;
+        match (success, is_switch) {
+            (true, true) => println!("Switched to your existing login with {}", proxy.green()),
+            (true, false) => {}
+            (false, true) => return Err(anyhow::anyhow!("Switching to {} failed", proxy)),
+            (false, false) => return Err(anyhow::anyhow!("Login failed")),
         }
 
         Ok(())
@@ -3,12 +3,19 @@ use clap::Parser;
 use colored::Colorize;
 
 use crate::teleport::cli;
+use crate::utils::dry_run;
 
 #[derive(Debug, Parser)]
 pub struct Logout {}
 
 impl Logout {
-    pub fn run(&self) -> Result<()> {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        if let Some(format) = beam.dry_run() {
+            return dry_run::print(
+                format,
+                &[dry_run::Command::tsh(None, &["logout".to_string()])],
+            );
+        }
         if cli::is_logged_in()? {
             cli::logout()?;
         } else {
@@ -1,8 +1,15 @@
+pub mod app;
+pub mod clusters;
 pub mod completions;
 pub mod configure;
 pub mod connect;
+pub mod db;
 pub mod default;
+pub mod doctor;
+pub mod kube;
 pub mod list;
 pub mod login;
 pub mod logout;
 pub mod profile;
+pub mod request;
+pub mod ssh_config;
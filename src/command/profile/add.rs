@@ -23,16 +23,11 @@ impl Add {
             }
         };
 
-        if self.profile.is_some()
-            && profiles
-                .iter()
-                .any(|p| &p.name == self.profile.as_ref().unwrap())
-        {
-            println!(
-                "Profile with name {} already exists",
-                self.profile.as_ref().unwrap().red()
-            );
-            process::exit(1);
+        if let Some(name) = &self.profile {
+            if profiles.iter().any(|p| &p.name == name) {
+                println!("Profile with name {} already exists", name.red());
+                process::exit(1);
+            }
         }
 
         let force_default = profiles.is_empty();
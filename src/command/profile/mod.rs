@@ -1,5 +1,7 @@
 mod add;
 mod remove;
+mod show;
+mod which;
 
 use anyhow::Result;
 use clap::Parser;
@@ -17,13 +19,22 @@ pub enum Command {
     /// Setting a configuration value
     #[clap(alias = "rm")]
     Remove(remove::Remove),
+    /// Showing the effective values of a profile and where they come from
+    Show(show::Show),
+    /// Showing which profile is used for a host and why
+    Which(which::Which),
 }
 
 impl Profile {
-    pub fn run(&self) -> Result<()> {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
         match &self.command {
-            Command::Add(cmd) => cmd.run(),
-            Command::Remove(cmd) => cmd.run(),
+            Command::Add(cmd) => {
+                beam.ensure_interactive("beam profile add")?;
+                cmd.run()
+            }
+            Command::Remove(cmd) => cmd.run(beam),
+            Command::Show(cmd) => cmd.run(beam),
+            Command::Which(cmd) => cmd.run(beam),
         }
     }
 }
@@ -14,7 +14,7 @@ pub struct Remove {
 }
 
 impl Remove {
-    pub fn run(&self) -> Result<()> {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
         let mut profiles = match Profiles::get() {
             Ok(profiles) => profiles,
             Err(err) => {
@@ -37,6 +37,7 @@ impl Remove {
                 profile.to_owned()
             }
             None => {
+                beam.ensure_interactive("Selecting a profile to remove")?;
                 // Get profile names
                 let profile_names = Profiles::get_names(&profiles)?;
 
@@ -55,6 +56,15 @@ impl Remove {
             }
         };
 
+        if let Some(profile) = profiles.iter().find(|p| p.name == profile_name) {
+            if let Err(err) = profile.ensure_writable() {
+                println!("{}", err);
+                process::exit(1);
+            }
+        }
+
+        let mut personal_profiles = Profiles::get_profiles()?;
+
         let is_default_profile = profiles.iter().any(|p| p.name == profile_name && p.default);
         if is_default_profile {
             if profiles.len() == 1 {
@@ -62,10 +72,16 @@ impl Remove {
                 process::exit(1);
             }
 
+            beam.ensure_interactive("Selecting a new default profile")?;
             println!("You are trying to remove the default profile. Please select a new default profile first.");
-            // Get profile names
+            // Only personal profiles can become the new default
+            profiles.retain(|p| !p.is_shared());
             let mut profile_names = Profiles::get_names(&profiles)?;
             profile_names.retain(|p| p != &format!("{} (default)", profile_name));
+            if profile_names.is_empty() {
+                println!("Cannot remove default profile, there is no other personal profile to make the default");
+                process::exit(1);
+            }
 
             let new_default_name_selection = Select::with_theme(&ColorfulTheme::default())
                 .with_prompt("Select new default profile")
@@ -76,17 +92,14 @@ impl Remove {
             let new_default_name = profile_names[new_default_name_selection].clone();
 
             // Set new default profile
-            profiles.iter_mut().for_each(|p| {
-                if p.name == new_default_name {
-                    p.default = true;
-                }
-            });
+            if let Some(p) = personal_profiles.profiles.get_mut(&new_default_name) {
+                p.default = true;
+            }
         }
 
-        // Remove profile by name from profiles
-        profiles.retain(|p| p.name != profile_name);
-        let profiles: Profiles = profiles.into();
-        match profiles.save() {
+        // Remove profile by name from the personal profiles
+        personal_profiles.profiles.remove(&profile_name);
+        match personal_profiles.save() {
             Ok(_) => {
                 println!("Profile {} removed", profile_name.green());
                 Ok(())
@@ -0,0 +1,75 @@
+use anyhow::Result;
+use clap::Parser;
+use colored::Colorize;
+
+use crate::utils::{
+    profile::Profile,
+    project::Project,
+    settings::{Setting, Settings, Source},
+};
+
+#[derive(Parser, Debug)]
+pub struct Show {
+    #[clap(help = "The profile to show. (default is the profile beam would use)")]
+    profile: Option<String>,
+}
+
+impl Show {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        let settings = match &self.profile {
+            Some(name) => Settings::from_profile(
+                beam,
+                &Project::get()?,
+                Setting {
+                    value: Profile::get(name)?,
+                    source: Source::Argument,
+                },
+            )?,
+            None => Settings::get(beam)?,
+        };
+
+        print_value(
+            "profile",
+            Some(&settings.profile.value.name),
+            Some(&settings.profile.source),
+        );
+        print_value(
+            "proxy",
+            settings.proxy.as_ref().map(|p| &p.value),
+            settings.proxy.as_ref().map(|p| &p.source),
+        );
+        print_value(
+            "user",
+            Some(&settings.user.value),
+            Some(&settings.user.source),
+        );
+        print_value(
+            "auth",
+            settings.auth.as_ref().map(|a| &a.value),
+            settings.auth.as_ref().map(|a| &a.source),
+        );
+        print_value(
+            "cluster",
+            settings.cluster.as_ref().map(|c| &c.value),
+            settings.cluster.as_ref().map(|c| &c.source),
+        );
+        print_value(
+            "cache_ttl",
+            Some(&settings.cache_ttl.value),
+            Some(&settings.cache_ttl.source),
+        );
+        Ok(())
+    }
+}
+
+fn print_value<T: ToString>(name: &str, value: Option<&T>, source: Option<&Source>) {
+    match (value, source) {
+        (Some(value), Some(source)) => println!(
+            "{:<10} {} ({})",
+            name,
+            value.to_string().green(),
+            source.to_string().dimmed()
+        ),
+        _ => println!("{:<10} {}", name, "not set".red()),
+    }
+}
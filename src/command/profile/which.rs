@@ -0,0 +1,75 @@
+use anyhow::Result;
+use clap::Parser;
+use colored::Colorize;
+use std::collections::HashMap;
+
+use crate::teleport::{cli, node, node::Node};
+use crate::utils::{profiles::Profiles, settings::Settings};
+
+#[derive(Parser, Debug)]
+pub struct Which {
+    #[clap(help = "The host to check the profiles against")]
+    host: String,
+}
+
+impl Which {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        let settings = Settings::get_for_node(beam, &Node::new(&self.host, HashMap::new()))?;
+        let proxy = settings.proxy()?;
+
+        cli::ensure_logged_in(proxy, settings.auth(), settings.teleport_user().as_deref())?;
+
+        let nodes = node::get(
+            !beam.clear_cache,
+            proxy,
+            &settings.listing(),
+            settings.cache_ttl.value,
+        )?;
+        let node = match nodes
+            .into_iter()
+            .find(|node| node.spec.hostname == self.host)
+        {
+            Some(node) => node,
+            None => {
+                println!(
+                    "{}",
+                    "Host not found in teleport, matching on the hostname only".yellow()
+                );
+                Node::new(&self.host, HashMap::new())
+            }
+        };
+
+        let candidates = Profiles::explain_matching(&node, Profiles::get()?)?;
+        for candidate in &candidates {
+            let priority = match candidate.profile.priority {
+                Some(priority) => format!("priority {}", priority),
+                None => "no priority".to_string(),
+            };
+            println!(
+                "{} {} ({}, specificity {}): {}",
+                if candidate.matched {
+                    "✔".green()
+                } else {
+                    "✘".red()
+                },
+                candidate.profile.name.cyan(),
+                priority,
+                candidate.profile.specificity(),
+                candidate.reason
+            );
+        }
+
+        match Profiles::explain_winner(&candidates) {
+            Some(reason) => println!(
+                "\nSelected profile {}, as {}",
+                candidates[0].profile.name.green(),
+                reason
+            ),
+            None => println!(
+                "\nNo profile matches {}, the default profile will be used",
+                self.host.green()
+            ),
+        }
+        Ok(())
+    }
+}
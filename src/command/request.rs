@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use colored::Colorize;
+use dialoguer::{theme::ColorfulTheme, Input};
+use std::{collections::HashMap, time::Duration};
+
+use crate::command::connect::Connect;
+use crate::teleport::cli;
+use crate::teleport::node::Node;
+use crate::teleport::request::{self, Review, State};
+use crate::utils::dry_run;
+use crate::utils::settings::{Settings, Source};
+
+#[derive(Debug, Parser)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Request {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(
+        help = "The role to request, or the host to request the request_role of its profile for"
+    )]
+    target: Option<String>,
+
+    #[clap(long, help = "The role to request for the host")]
+    role: Option<String>,
+
+    #[clap(long, help = "Why you need access")]
+    reason: Option<String>,
+
+    #[clap(
+        long,
+        default_value_t = 600,
+        help = "How many seconds to wait for the request to be reviewed"
+    )]
+    timeout: u64,
+}
+
+#[derive(Debug, Parser)]
+pub enum Command {
+    /// Showing your pending and approved access requests
+    Ls,
+}
+
+impl Request {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        match (&self.command, &self.target) {
+            (Some(Command::Ls), _) => Request::list(beam),
+            (None, Some(target)) => self.create(beam, target),
+            (None, None) => Err(anyhow!("Please name a role or host to request access for")),
+        }
+    }
+
+    fn create(&self, beam: &crate::cli::Beam, target: &str) -> Result<()> {
+        // The node may not be listed without the requested role, so profiles are matched on the hostname only
+        let settings = Settings::get_for_node(beam, &Node::new(target, HashMap::new()))?;
+        let proxy = settings.proxy()?;
+
+        let profile = &settings.profile.value;
+        let (role, host) = match (&self.role, &settings.profile.source) {
+            (Some(role), _) => (role.to_owned(), Some(target)),
+            // A host matching a profile is never taken for the name of a role
+            (None, Source::Matched) => match &profile.config.request_role {
+                Some(role) => (role.to_owned(), Some(target)),
+                None => {
+                    return Err(anyhow!(
+                        "No request_role for host {} in profile {}, use --role",
+                        target,
+                        profile.name
+                    ))
+                }
+            },
+            (None, _) => (target.to_string(), None),
+        };
+
+        if let Some(format) = beam.dry_run() {
+            // Logging in with the request and connecting need the ID of the created request
+            let args = cli::request_create_args(proxy, &role, self.reason.as_ref());
+            return dry_run::print(format, &[dry_run::Command::tsh(Some(proxy), &args)]);
+        }
+
+        cli::ensure_logged_in(proxy, settings.auth(), settings.teleport_user().as_deref())?;
+
+        let reason = match &self.reason {
+            Some(reason) => Some(reason.to_owned()),
+            None if beam.is_interactive() => Some(
+                Input::<String>::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Reason")
+                    .allow_empty(true)
+                    .interact_text()?,
+            )
+            .filter(|reason| !reason.is_empty()),
+            None => None,
+        };
+
+        let request_id = request::create(proxy, &role, reason.as_ref())?;
+        println!(
+            "Requested role {} with access request {}",
+            role.green(),
+            request_id.cyan()
+        );
+        let timeout = Duration::from_secs(self.timeout);
+        match request::wait_for_approval(proxy, &request_id, timeout)? {
+            Review::Approved(_) => {}
+            Review::Denied => return Err(anyhow!("Access request {} was denied", request_id)),
+            Review::Expired => {
+                return Err(anyhow!(
+                    "Access request {} expired before it was reviewed",
+                    request_id
+                ))
+            }
+        }
+
+        if !cli::login_with_request(proxy, &request_id)? {
+            return Err(anyhow!("Login with access request {} failed", request_id));
+        }
+        println!("Access request {} was approved", request_id.green());
+
+        match host {
+            // The nodes reachable with the new role are not cached yet
+            Some(host) => Connect::new(host).run_uncached(beam),
+            None => Ok(()),
+        }
+    }
+
+    fn list(beam: &crate::cli::Beam) -> Result<()> {
+        let settings = Settings::get(beam)?;
+        let proxy = settings.proxy()?;
+        if let Some(format) = beam.dry_run() {
+            let args = cli::request_ls_args(proxy);
+            return dry_run::print(format, &[dry_run::Command::tsh(Some(proxy), &args)]);
+        }
+
+        cli::ensure_logged_in(proxy, settings.auth(), settings.teleport_user().as_deref())?;
+
+        let requests: Vec<_> = request::list(proxy)?
+            .into_iter()
+            .filter(|request| matches!(request.state(), State::Pending | State::Approved))
+            .collect();
+        if requests.is_empty() {
+            println!("No pending or approved access requests");
+            return Ok(());
+        }
+
+        println!(
+            "{:<38} {:<10} {:<20} {}",
+            "ID".bold(),
+            "State".bold(),
+            "Roles".bold(),
+            "Reason".bold()
+        );
+        for request in requests {
+            let state = match request.state() {
+                State::Approved => request.state().to_string().green(),
+                state => state.to_string().yellow(),
+            };
+            println!(
+                "{:<38} {:<10} {:<20} {}",
+                request.id(),
+                state,
+                request.spec.roles.join(","),
+                request.spec.request_reason.clone().unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use regex::Regex;
+
+use crate::ssh;
+use crate::teleport::{cli, node};
+use crate::utils::settings::{Settings, Source};
+
+#[derive(Debug, Parser)]
+pub struct SshConfig {
+    #[clap(
+        help = "A hostname regex or labels like env=prod,team=payments the hosts must match. (default is all hosts)"
+    )]
+    selector: Option<String>,
+}
+
+impl SshConfig {
+    pub fn run(&self, beam: &crate::cli::Beam) -> Result<()> {
+        let settings = Settings::get(beam)?;
+        // The proxy stays the same, even if a node matches a profile with another proxy
+        let proxy = &settings.proxy()?.to_owned();
+
+        cli::ensure_logged_in(proxy, settings.auth(), settings.teleport_user().as_deref())?;
+
+        let mut nodes = node::get(
+            !beam.clear_cache,
+            proxy,
+            &settings.listing(),
+            settings.cache_ttl.value,
+        )?;
+        if let Some(label_filter) = &settings.profile.value.config.label_filter {
+            nodes.retain(|node| node.has_labels(label_filter));
+        }
+        match &self.selector {
+            Some(selector) if selector.contains('=') => {
+                let labels = crate::cli::parse_labels(selector).map_err(|err| anyhow!(err))?;
+                nodes.retain(|node| node.has_labels(&labels));
+            }
+            Some(selector) => {
+                let pattern = Regex::new(&format!("^(?:{})$", selector))
+                    .context(format!("{} is not a valid hostname regex", selector))?;
+                nodes.retain(|node| pattern.is_match(&node.spec.hostname));
+            }
+            None => {}
+        }
+        if nodes.is_empty() {
+            return Err(anyhow!("No host in teleport matches the selector"));
+        }
+        nodes.sort_by(|a, b| a.spec.hostname.cmp(&b.spec.hostname));
+
+        let tsh_profile = cli::valid_profile(proxy)?;
+        let mut entries = vec![format!("# Generated by beam for {}", proxy)];
+        for node in &nodes {
+            // An explicitly selected profile is kept, otherwise a profile matching the node is used
+            let node_settings = match settings.profile.source {
+                Source::Flag | Source::Env(_) => None,
+                _ => Some(Settings::get_for_node(beam, node)?),
+            };
+            let node_settings = node_settings.as_ref().unwrap_or(&settings);
+            let login = node_settings.login(node)?;
+            let session = ssh::connect::Session::new(
+                &node.spec.hostname,
+                &login.value,
+                proxy,
+                node.cluster.as_deref(),
+                &node_settings.profile.value,
+            )?;
+            entries.push(session.ssh_config(tsh_profile.as_ref()));
+        }
+
+        println!("{}", entries.join("\n"));
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 pub mod cli;
 mod command;
 mod ssh;
-mod teleport;
-mod utils;
+pub mod teleport;
+pub mod utils;
@@ -1,60 +1,217 @@
 use anyhow::{Context, Result};
 use colored::Colorize;
-use std::process::{Command, ExitStatus};
 
+use std::path::Path;
+
+use crate::teleport::backend;
+use crate::teleport::cli::{self, TshProfile};
 use crate::utils::profile::Profile;
+use crate::utils::{dry_run, shell};
+
+/// The port teleport nodes listen on for ssh
+const TELEPORT_SSH_PORT: u16 = 3022;
 
-pub fn connect(mut tsh_args: Vec<String>) -> Result<ExitStatus> {
-    tsh_args.remove(0);
-    let mut process = Command::new("tsh").args(tsh_args).spawn()?;
+/// Runs `tsh ssh` with the arguments from [`Session::ssh_args`] and returns whether the session ended successfully
+pub fn connect(proxy: &str, ssh_args: &[String]) -> Result<bool> {
+    backend::get().ssh(proxy, ssh_args)
+}
+
+/// A port forwarded from the local machine through the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forward {
+    pub listen_port: u16,
+    pub remote_host: String,
+    pub remote_port: u16,
+}
 
-    process.wait().map_err(|e| anyhow::anyhow!(e))
+/// An ssh session to a host, as resolved from the profile. Both the tsh command and the OpenSSH config
+/// are rendered from it, so they always agree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub host: String,
+    pub login: String,
+    pub proxy: String,
+    pub cluster: Option<String>,
+    pub forward: Option<Forward>,
 }
 
-pub fn get_tsh_command(host: &str, username: &str, profile: &Profile) -> Result<Vec<String>> {
-    let host_string = format!("{}@{}", username, host);
+impl Session {
+    pub fn new(
+        host: &str,
+        username: &str,
+        proxy: &str,
+        cluster: Option<&str>,
+        profile: &Profile,
+    ) -> Result<Session> {
+        Ok(Session {
+            host: host.to_string(),
+            login: username.to_string(),
+            proxy: proxy.to_string(),
+            cluster: cluster.map(str::to_string),
+            forward: get_forward(profile)?,
+        })
+    }
+
+    /// The arguments of `tsh ssh` connecting to the host
+    pub fn ssh_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = vec![format!("--proxy={}", self.proxy)];
+        if let Some(cluster) = &self.cluster {
+            args.push(format!("--cluster={}", cluster));
+        }
+        if let Some(forward) = &self.forward {
+            args.push("-L".into());
+            args.push(format!(
+                "{}:{}:{}",
+                forward.listen_port, forward.remote_host, forward.remote_port
+            ));
+        }
+        args.push(format!("{}@{}", self.login, self.host));
+        args
+    }
+
+    /// The full command line of [`Session::ssh_args`], as printed by the dry run
+    pub fn tsh_command(&self) -> Vec<String> {
+        backend::tsh(Some(&self.proxy)).command_line("ssh", &self.ssh_args())
+    }
+
+    /// The options of OpenSSH connecting to the host through `tsh proxy ssh`.
+    /// With the login of tsh for the proxy, they also use its certificate
+    pub fn ssh_options(&self, tsh_profile: Option<&TshProfile>) -> Vec<(&'static str, String)> {
+        let mut proxy_args = vec!["ssh".to_string(), format!("--proxy={}", self.proxy)];
+        if let Some(cluster) = &self.cluster {
+            proxy_args.push(format!("--cluster={}", cluster));
+        }
+        proxy_args.push("%r@%h:%p".to_string());
+        let proxy_command = backend::tsh(Some(&self.proxy)).command_line("proxy", &proxy_args);
+
+        let mut options = vec![
+            ("HostName", self.host.clone()),
+            ("User", self.login.clone()),
+            ("Port", TELEPORT_SSH_PORT.to_string()),
+            ("ProxyCommand", shell::join(&proxy_command)),
+        ];
+        if let Some(forward) = &self.forward {
+            options.push((
+                "LocalForward",
+                format!(
+                    "{} {}:{}",
+                    forward.listen_port, forward.remote_host, forward.remote_port
+                ),
+            ));
+        }
+        if let (Some(tsh_profile), Ok(tsh_home)) = (tsh_profile, cli::tsh_home()) {
+            let keys_dir = tsh_home.join("keys").join(&tsh_profile.proxy);
+            let cluster = self.cluster.as_ref().unwrap_or(&tsh_profile.cluster);
+            options.push((
+                "UserKnownHostsFile",
+                config_path(&tsh_home.join("known_hosts")),
+            ));
+            options.push((
+                "IdentityFile",
+                config_path(&keys_dir.join(&tsh_profile.user)),
+            ));
+            options.push((
+                "CertificateFile",
+                config_path(
+                    &keys_dir
+                        .join(format!("{}-ssh", tsh_profile.user))
+                        .join(format!("{}-cert.pub", cluster)),
+                ),
+            ));
+        }
+        options
+    }
+
+    /// A `Host` entry for `~/.ssh/config`
+    pub fn ssh_config(&self, tsh_profile: Option<&TshProfile>) -> String {
+        let mut lines = vec![format!("Host {}", self.host)];
+        for (key, value) in self.ssh_options(tsh_profile) {
+            lines.push(format!("    {} {}", key, value));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// The ssh command of OpenSSH equivalent to [`Session::tsh_command`]
+    pub fn openssh_command(&self, tsh_profile: Option<&TshProfile>) -> Vec<String> {
+        let mut command = vec!["ssh".to_string()];
+        for (key, value) in self.ssh_options(tsh_profile) {
+            // The host and login are given as the destination
+            if key != "HostName" && key != "User" {
+                command.push("-o".to_string());
+                command.push(format!("{}={}", key, value));
+            }
+        }
+        command.push(format!("{}@{}", self.login, self.host));
+        command
+    }
+
+    /// Prints the command connecting to the host in the format, instead of running it
+    pub fn dry_run(&self, format: dry_run::Format) -> Result<()> {
+        match format {
+            dry_run::Format::Openssh => {
+                let tsh_profile = cli::valid_profile(&self.proxy)?;
+                println!(
+                    "{}",
+                    shell::join(&self.openssh_command(tsh_profile.as_ref()))
+                );
+                Ok(())
+            }
+            format => dry_run::print(
+                format,
+                &[dry_run::Command {
+                    argv: self.tsh_command(),
+                    is_tsh: true,
+                }],
+            ),
+        }
+    }
+}
 
-    let mut args: Vec<String> = vec!["tsh".into(), "ssh".into()];
-    let port_forwarding_string;
+/// Paths in the config of OpenSSH need quotes, when they contain whitespace
+fn config_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    match path.contains(char::is_whitespace) {
+        true => format!("\"{}\"", path),
+        false => path,
+    }
+}
 
-    if profile.config.enable_port_forwarding.is_some()
-        && profile.config.enable_port_forwarding.unwrap()
-    {
-        let listen_port = profile.config.listen_port.context(
-            format!(
+fn get_forward(profile: &Profile) -> Result<Option<Forward>> {
+    if profile.config.enable_port_forwarding != Some(true) {
+        return Ok(None);
+    }
+    let listen_port = profile.config.listen_port.context(
+        format!(
             "port forwarding was activated for profile {}, but listen_port property was not set",
             profile.name.cyan()
         )
-            .red(),
-        )?;
-        let remote_host = profile.config.remote_host.as_ref().context(
-            format!(
+        .red(),
+    )?;
+    let remote_host = profile.config.remote_host.clone().context(
+        format!(
             "port forwarding was activated for profile {}, but remote_host property was not set",
             profile.name.cyan()
         )
-            .red(),
-        )?;
-        let remote_port = profile.config.remote_port.context(
-            format!(
+        .red(),
+    )?;
+    let remote_port = profile.config.remote_port.context(
+        format!(
             "port forwarding was activated for profile {}, but remote_port property was not set",
             profile.name.cyan()
         )
-            .red(),
-        )?;
-        args.push("-L".into());
-        port_forwarding_string = format!("{}:{}:{}", listen_port, remote_host, remote_port);
-        args.push(port_forwarding_string);
-    }
-
-    args.push(host_string);
-
-    Ok(args)
+        .red(),
+    )?;
+    Ok(Some(Forward {
+        listen_port,
+        remote_host,
+        remote_port,
+    }))
 }
 
 #[cfg(test)]
 mod tests {
     #[test]
-    fn test_get_tsh_command_port_forwarding() {
+    fn test_tsh_command_port_forwarding() {
         use super::*;
         use crate::utils::config::Config;
         use crate::utils::profile::Profile;
@@ -73,23 +230,28 @@ mod tests {
                 auth: None,
                 cache_ttl: None,
                 label_whitelist: None,
+                ..Default::default()
             },
             default: true,
             host_pattern: None,
             priority: None,
+            ..Default::default()
         };
 
-        let args = get_tsh_command("t-test", username, &profile).unwrap();
+        let args = Session::new("t-test", username, "teleport.example.com", None, &profile)
+            .unwrap()
+            .tsh_command();
 
         assert_eq!(args[0], "tsh");
         assert_eq!(args[1], "ssh");
-        assert_eq!(args[2], "-L");
-        assert_eq!(args[3], "8080:localhost:80");
-        assert_eq!(args[4], "testuser@t-test");
+        assert_eq!(args[2], "--proxy=teleport.example.com");
+        assert_eq!(args[3], "-L");
+        assert_eq!(args[4], "8080:localhost:80");
+        assert_eq!(args[5], "testuser@t-test");
     }
 
     #[test]
-    fn test_get_tsh_command() {
+    fn test_tsh_command() {
         use super::*;
         use crate::utils::config::Config;
         use crate::utils::profile::Profile;
@@ -108,16 +270,75 @@ mod tests {
                 auth: None,
                 cache_ttl: None,
                 label_whitelist: None,
+                ..Default::default()
             },
             default: true,
             host_pattern: None,
             priority: None,
+            ..Default::default()
         };
 
-        let args = get_tsh_command("t-test", username, &profile).unwrap();
+        let args = Session::new("t-test", username, "teleport.example.com", None, &profile)
+            .unwrap()
+            .tsh_command();
 
         assert_eq!(args[0], "tsh");
         assert_eq!(args[1], "ssh");
-        assert_eq!(args[2], "testuser@t-test");
+        assert_eq!(args[2], "--proxy=teleport.example.com");
+        assert_eq!(args[3], "testuser@t-test");
+
+        let args = Session::new(
+            "t-test",
+            username,
+            "teleport.example.com",
+            Some("leaf"),
+            &profile,
+        )
+        .unwrap()
+        .ssh_args();
+        assert_eq!(
+            args,
+            vec![
+                "--proxy=teleport.example.com",
+                "--cluster=leaf",
+                "testuser@t-test"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ssh_config() {
+        use super::*;
+
+        let session = Session {
+            host: "db-1".into(),
+            login: "ec2-user".into(),
+            proxy: "teleport.example.com".into(),
+            cluster: Some("leaf".into()),
+            forward: Some(Forward {
+                listen_port: 5432,
+                remote_host: "localhost".into(),
+                remote_port: 5432,
+            }),
+        };
+
+        let config = session.ssh_config(None);
+        assert_eq!(
+            config,
+            "Host db-1
+    HostName db-1
+    User ec2-user
+    Port 3022
+    ProxyCommand tsh proxy ssh --proxy=teleport.example.com --cluster=leaf %r@%h:%p
+    LocalForward 5432 localhost:5432
+"
+        );
+
+        assert_eq!(
+            shell::join(&session.openssh_command(None)),
+            "ssh -o Port=3022 \
+             -o 'ProxyCommand=tsh proxy ssh --proxy=teleport.example.com --cluster=leaf %r@%h:%p' \
+             -o 'LocalForward=5432 localhost:5432' ec2-user@db-1"
+        );
     }
 }
@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use dialoguer::{theme::ColorfulTheme, Select};
+
+use crate::cli::Beam;
+use crate::teleport::cli;
+use crate::utils::settings::{Setting, Source};
+
+/// The key to accept a host in the picker with, to choose the login afterwards
+pub const CHOOSE_LOGIN_KEY: &str = "ctrl-o";
+
+/// Checks the login against the logins allowed by the teleport roles of the user.
+/// An explicitly given login, which is not allowed, is refused, a configured one only causes a warning.
+/// With `choose`, the user picks one of the allowed logins instead
+pub fn check(
+    beam: &Beam,
+    proxy: &str,
+    login: Setting<String>,
+    choose: bool,
+) -> Result<Setting<String>> {
+    let allowed = cli::logins(proxy)?;
+
+    if choose {
+        beam.ensure_interactive("Choosing a login")?;
+        if allowed.is_empty() {
+            return Err(anyhow!("Teleport did not report any allowed logins"));
+        }
+        let default = allowed
+            .iter()
+            .position(|allowed_login| allowed_login == &login.value)
+            .unwrap_or(0);
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select a login")
+            .default(default)
+            .items(&allowed)
+            .interact()?;
+        return Ok(Setting {
+            value: allowed[selection].clone(),
+            source: Source::Chosen,
+        });
+    }
+
+    // Without any logins reported, e.g. for an older tsh, there is nothing to check against
+    if allowed.is_empty() || allowed.contains(&login.value) {
+        return Ok(login);
+    }
+
+    let message = format!(
+        "Login {} from {} is not allowed by your teleport roles. Allowed logins are {}",
+        login.value,
+        login.source,
+        allowed.join(", ")
+    );
+    match login.source {
+        Source::Flag | Source::Env(_) => Err(anyhow!(message)),
+        _ => {
+            eprintln!("{} {}", "Warning:".yellow(), message);
+            Ok(login)
+        }
+    }
+}
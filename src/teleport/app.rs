@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::teleport::cli::Feature;
+use crate::teleport::resource::{Metadata, Resource};
+
+/// An application teleport gives access to, as listed by `tsh apps ls --format=json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct App {
+    metadata: Metadata,
+    spec: Spec,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spec {
+    #[serde(default)]
+    pub public_addr: String,
+}
+
+impl Resource for App {
+    const SUBCOMMAND: &'static str = "apps";
+    const PLURAL: &'static str = "apps";
+    const FEATURE: Feature = Feature::Apps;
+
+    fn name(&self) -> &str {
+        &self.metadata.name
+    }
+
+    fn labels(&self) -> &HashMap<String, String> {
+        &self.metadata.labels
+    }
+
+    fn details(&self) -> Vec<String> {
+        vec![self.spec.public_addr.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let apps: Vec<App> = serde_json::from_str(
+            r#"[
+  {"kind": "app", "version": "v3", "metadata": {"name": "grafana", "description": "Dashboards", "labels": {"env": "prod"}}, "spec": {"uri": "http://localhost:3000", "public_addr": "grafana.teleport.example.com"}}
+]"#,
+        )
+        .unwrap();
+        assert_eq!(apps[0].name(), "grafana");
+        assert_eq!(apps[0].details(), vec!["grafana.teleport.example.com"]);
+    }
+}
@@ -0,0 +1,387 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use semver::Version;
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use crate::teleport::cli::{self, TshProfile};
+use crate::teleport::node::Filter;
+use crate::utils::{profile::Profile, settings::TSH_ENV};
+
+lazy_static! {
+    static ref BACKEND: RwLock<Arc<dyn TeleportBackend>> = RwLock::new(Arc::new(Tsh));
+    static ref TSH: RwLock<TshCommands> = RwLock::new(TshCommands::default());
+    static ref VERSIONS: Mutex<HashMap<TshCommand, Version>> = Mutex::new(HashMap::new());
+}
+
+/// Replaces the backend used for all teleport operations, e.g. with a
+/// [`FakeBackend`](crate::teleport::fake::FakeBackend) in tests
+pub fn set_backend(backend: Arc<dyn TeleportBackend>) {
+    *BACKEND.write().unwrap() = backend;
+}
+
+pub fn get() -> Arc<dyn TeleportBackend> {
+    BACKEND.read().unwrap().clone()
+}
+
+/// The tsh executable and the arguments passed to every call of it, e.g. `--insecure`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TshCommand {
+    pub path: String,
+    pub args: Vec<String>,
+}
+
+impl Default for TshCommand {
+    /// `BEAM_TSH` or tsh from the `PATH`
+    fn default() -> TshCommand {
+        TshCommand {
+            path: tsh_env().unwrap_or_else(|| "tsh".to_string()),
+            args: vec![],
+        }
+    }
+}
+
+impl TshCommand {
+    /// The tsh of the profile. `BEAM_TSH` wins over its `tsh_path`
+    pub fn for_profile(profile: &Profile) -> TshCommand {
+        let config = &profile.config;
+        TshCommand {
+            path: tsh_env()
+                .or_else(|| config.tsh_path.clone())
+                .unwrap_or_else(|| "tsh".to_string()),
+            args: config.tsh_args.clone().unwrap_or_default(),
+        }
+    }
+
+    /// The full command line running the tsh subcommand with the arguments
+    pub fn command_line(&self, subcommand: &str, args: &[String]) -> Vec<String> {
+        let mut command_line = vec![self.path.clone()];
+        command_line.extend(self.args.iter().cloned());
+        command_line.push(subcommand.to_string());
+        command_line.extend(args.iter().cloned());
+        command_line
+    }
+
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        command.args(&self.args);
+        command
+    }
+}
+
+fn tsh_env() -> Option<String> {
+    std::env::var(TSH_ENV)
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// The tsh to use for each proxy and for calls not concerning a specific proxy
+#[derive(Debug, Default)]
+struct TshCommands {
+    default: Option<TshCommand>,
+    by_proxy: HashMap<String, TshCommand>,
+}
+
+/// Uses the tsh for the proxy. Without a proxy or for the proxy resolved last, it also becomes the default,
+/// e.g. for `tsh status`
+pub fn set_tsh(proxy: Option<&str>, tsh: TshCommand) {
+    let mut commands = TSH.write().unwrap();
+    if let Some(proxy) = proxy {
+        commands
+            .by_proxy
+            .insert(cli::proxy_host(proxy).to_string(), tsh.clone());
+    }
+    commands.default = Some(tsh);
+}
+
+/// Registers the tsh for the proxy, without changing the default
+pub fn add_tsh(proxy: &str, tsh: TshCommand) {
+    TSH.write()
+        .unwrap()
+        .by_proxy
+        .insert(cli::proxy_host(proxy).to_string(), tsh);
+}
+
+/// The tsh to use for the proxy
+pub fn tsh(proxy: Option<&str>) -> TshCommand {
+    let commands = TSH.read().unwrap();
+    proxy
+        .and_then(|proxy| commands.by_proxy.get(cli::proxy_host(proxy)))
+        .or(commands.default.as_ref())
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoginOptions {
+    pub proxy: String,
+    pub user: Option<String>,
+    pub auth: Option<String>,
+    /// An approved access request, whose roles to assume
+    pub request_id: Option<String>,
+    /// Hides the output of tsh, e.g. when only switching to an existing login
+    pub quiet: bool,
+}
+
+impl LoginOptions {
+    /// The arguments of `tsh login`
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["login".to_string(), format!("--proxy={}", self.proxy)];
+        if let Some(user) = &self.user {
+            args.push(format!("--user={}", user));
+        }
+        if let Some(auth) = &self.auth {
+            args.push(format!("--auth={}", auth));
+        }
+        if let Some(request_id) = &self.request_id {
+            args.push(format!("--request-id={}", request_id));
+        }
+        args
+    }
+}
+
+/// The arguments of `tsh ls` listing the nodes of the proxy in the format
+pub fn ls_args(proxy: &str, cluster: Option<&str>, filter: &Filter, format: &str) -> Vec<String> {
+    let mut args = vec![
+        "ls".to_string(),
+        format!("--proxy={}", proxy),
+        "-f".to_string(),
+        format.to_string(),
+    ];
+    if let Some(cluster) = cluster {
+        args.push(format!("--cluster={}", cluster));
+    }
+    args.extend(filter.tsh_args());
+    args
+}
+
+/// What tsh printed, for the calls whose output beam reads, e.g. `tsh request ls`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TshOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// The operations beam runs against teleport. The exit status of tsh is returned as whether it succeeded
+pub trait TeleportBackend: Send + Sync {
+    /// The profiles of all proxies tsh is or was logged in with
+    fn status(&self) -> Result<Vec<TshProfile>>;
+    fn login(&self, options: &LoginOptions) -> Result<bool>;
+    fn logout(&self) -> Result<bool>;
+    /// Lists the nodes of the proxy in the given format of `tsh ls`
+    fn ls(
+        &self,
+        proxy: &str,
+        cluster: Option<&str>,
+        filter: &Filter,
+        format: &str,
+    ) -> Result<String>;
+    /// Lists resources like databases through `tsh <subcommand> ls --format=json`
+    fn resources(
+        &self,
+        subcommand: &str,
+        proxy: &str,
+        cluster: Option<&str>,
+        filter: &Filter,
+    ) -> Result<String>;
+    /// Runs tsh with the arguments in the foreground, e.g. `db connect`
+    fn run(&self, proxy: &str, args: &[String]) -> Result<bool>;
+    /// Runs `tsh ssh` with the arguments
+    fn ssh(&self, proxy: &str, args: &[String]) -> Result<bool>;
+    /// Runs `tsh scp` with the arguments
+    fn scp(&self, proxy: &str, args: &[String]) -> Result<bool>;
+    /// Runs tsh with the arguments and captures what it prints, e.g. `request create`
+    fn output(&self, proxy: &str, args: &[String]) -> Result<TshOutput>;
+    /// The version of the tsh for the proxy
+    fn version(&self, proxy: Option<&str>) -> Result<Version>;
+}
+
+/// Runs the teleport cli
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tsh;
+
+impl Tsh {
+    fn spawn(&self, proxy: Option<&str>, args: &[String], quiet: bool) -> Result<bool> {
+        let mut command = tsh(proxy).command();
+        command.args(args);
+        if quiet {
+            command.stdout(Stdio::null());
+        }
+        let mut process = command.spawn().map_err(|_| cli::tsh_not_found())?;
+        Ok(process.wait()?.success())
+    }
+}
+
+impl TeleportBackend for Tsh {
+    fn status(&self) -> Result<Vec<TshProfile>> {
+        let output = tsh(None)
+            .command()
+            .args(["status"])
+            .output()
+            .map_err(|_| cli::tsh_not_found())?;
+        Ok(cli::parse_status(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    fn login(&self, options: &LoginOptions) -> Result<bool> {
+        self.spawn(Some(&options.proxy), &options.args(), options.quiet)
+    }
+
+    fn logout(&self) -> Result<bool> {
+        self.spawn(None, &["logout".to_string()], false)
+    }
+
+    fn ls(
+        &self,
+        proxy: &str,
+        cluster: Option<&str>,
+        filter: &Filter,
+        format: &str,
+    ) -> Result<String> {
+        let output = tsh(Some(proxy))
+            .command()
+            .args(ls_args(proxy, cluster, filter, format))
+            .output()
+            .map_err(|_| cli::tsh_not_found())?;
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn resources(
+        &self,
+        subcommand: &str,
+        proxy: &str,
+        cluster: Option<&str>,
+        filter: &Filter,
+    ) -> Result<String> {
+        let mut args = vec![
+            subcommand.to_string(),
+            "ls".to_string(),
+            format!("--proxy={}", proxy),
+            "--format=json".to_string(),
+        ];
+        if let Some(cluster) = cluster {
+            args.push(format!("--cluster={}", cluster));
+        }
+        args.extend(filter.tsh_args());
+        let output = tsh(Some(proxy))
+            .command()
+            .args(args)
+            .output()
+            .map_err(|_| cli::tsh_not_found())?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Could not list the resources of tsh {}: {}",
+                subcommand,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn run(&self, proxy: &str, args: &[String]) -> Result<bool> {
+        self.spawn(Some(proxy), args, false)
+    }
+
+    fn ssh(&self, proxy: &str, args: &[String]) -> Result<bool> {
+        let mut ssh_args = vec!["ssh".to_string()];
+        ssh_args.extend_from_slice(args);
+        self.spawn(Some(proxy), &ssh_args, false)
+    }
+
+    fn scp(&self, proxy: &str, args: &[String]) -> Result<bool> {
+        let mut scp_args = vec!["scp".to_string()];
+        scp_args.extend_from_slice(args);
+        self.spawn(Some(proxy), &scp_args, false)
+    }
+
+    fn output(&self, proxy: &str, args: &[String]) -> Result<TshOutput> {
+        let output = tsh(Some(proxy))
+            .command()
+            .args(args)
+            .output()
+            .map_err(|_| cli::tsh_not_found())?;
+        Ok(TshOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+
+    /// Runs `tsh version` once per tsh
+    fn version(&self, proxy: Option<&str>) -> Result<Version> {
+        let tsh = tsh(proxy);
+        if let Some(version) = VERSIONS.lock().unwrap().get(&tsh) {
+            return Ok(version.clone());
+        }
+        let output = tsh
+            .command()
+            .args(["version"])
+            .output()
+            .map_err(|_| cli::tsh_not_found())?;
+        let version = cli::parse_version(&String::from_utf8_lossy(&output.stdout))?;
+        VERSIONS.lock().unwrap().insert(tsh, version.clone());
+        Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::config::Config;
+
+    #[test]
+    fn test_tsh_command_for_profile() {
+        let profile = Profile {
+            config: Config {
+                tsh_path: Some("/opt/teleport-9/tsh".to_owned()),
+                tsh_args: Some(vec!["--insecure".to_owned()]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let tsh = TshCommand::for_profile(&profile);
+        assert_eq!(
+            tsh.command_line("ssh", &["root@web-1".to_owned()]),
+            vec!["/opt/teleport-9/tsh", "--insecure", "ssh", "root@web-1"]
+        );
+        assert_eq!(
+            TshCommand::for_profile(&Profile::default()),
+            TshCommand {
+                path: "tsh".to_owned(),
+                args: vec![]
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_version_per_proxy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for (proxy, version) in [("old.example.com", "9.3.0"), ("new.example.com", "15.0.0")] {
+            let path = dir.path().join(format!("tsh-{}", version));
+            std::fs::write(&path, format!("#!/bin/sh\necho Teleport v{}\n", version)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            add_tsh(
+                proxy,
+                TshCommand {
+                    path: path.display().to_string(),
+                    args: vec![],
+                },
+            );
+        }
+
+        assert_eq!(
+            Tsh.version(Some("old.example.com")).unwrap(),
+            Version::new(9, 3, 0)
+        );
+        assert_eq!(
+            Tsh.version(Some("new.example.com")).unwrap(),
+            Version::new(15, 0, 0)
+        );
+    }
+}
@@ -1,72 +1,432 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
-use std::process::{Command, ExitStatus};
+use semver::Version;
+use std::path::PathBuf;
 
-use crate::utils::spinner;
+use crate::teleport::backend::{self, LoginOptions};
+use crate::teleport::node::Filter;
 
-pub fn is_logged_in() -> Result<bool> {
-    let output = match Command::new("tsh").args(["status"]).output() {
-        Ok(output) => output,
-        Err(_) => {
-            return Err(anyhow::anyhow!("Unable to access the teleport cli.\nPlease make sure, that the teleport cli is installed and available in your PATH. For further information see the teleport documentation: https://goteleport.com/docs/installation/".red()));
+pub(crate) const TELEPORT_HOME_ENV: &str = "TELEPORT_HOME";
+
+pub(crate) fn tsh_not_found() -> anyhow::Error {
+    anyhow::anyhow!("Unable to access the teleport cli.\nPlease make sure, that the teleport cli is installed and available in your PATH, or point beam to it with tsh_path in your profile or BEAM_TSH. For further information see the teleport documentation: https://goteleport.com/docs/installation/".red())
+}
+
+/// A profile tsh keeps for a proxy, holding the certificate of the user
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TshProfile {
+    /// The host of the proxy, without scheme and port
+    pub proxy: String,
+    /// The teleport user the certificate was issued for
+    pub user: String,
+    /// The root cluster of the proxy
+    pub cluster: String,
+    /// Whether this is the profile tsh uses, when no proxy is given
+    pub active: bool,
+    /// Whether the certificate has not expired yet
+    pub valid: bool,
+    pub logins: Vec<String>,
+}
+
+impl TshProfile {
+    pub fn is_for(&self, proxy: &str) -> bool {
+        self.proxy == proxy_host(proxy)
+    }
+}
+
+/// The directory tsh keeps its profiles and keys in
+pub(crate) fn tsh_home() -> Result<PathBuf> {
+    match std::env::var_os(TELEPORT_HOME_ENV).filter(|home| !home.is_empty()) {
+        Some(home) => Ok(PathBuf::from(home)),
+        None => Ok(home::home_dir()
+            .context("Could not find home directory")?
+            .join(".tsh")),
+    }
+}
+
+/// The host of a proxy address like `teleport.example.com:443`
+pub(crate) fn proxy_host(proxy: &str) -> &str {
+    let proxy = proxy.split("//").last().unwrap_or(proxy);
+    proxy.split(':').next().unwrap_or(proxy)
+}
+
+/// The profiles of all proxies tsh is or was logged in with
+pub fn profiles() -> Result<Vec<TshProfile>> {
+    backend::get().status()
+}
+
+/// Parses the output of `tsh status`, which lists the active profile, marked with `>`, followed by the others
+pub(crate) fn parse_status(output: &str) -> Vec<TshProfile> {
+    let mut profiles: Vec<TshProfile> = vec![];
+    for line in output.lines() {
+        let active = line.starts_with('>');
+        let line = line.trim_start_matches('>').trim();
+        if let Some(url) = line.strip_prefix("Profile URL:") {
+            profiles.push(TshProfile {
+                proxy: proxy_host(url.trim()).to_string(),
+                active,
+                ..Default::default()
+            });
+        } else if let Some(profile) = profiles.last_mut() {
+            if let Some(user) = line.strip_prefix("Logged in as:") {
+                profile.user = user.trim().to_string();
+            } else if let Some(cluster) = line.strip_prefix("Cluster:") {
+                profile.cluster = cluster.trim().to_string();
+            } else if let Some(valid_until) = line.strip_prefix("Valid until:") {
+                profile.valid = valid_until.contains("valid for");
+            } else if let Some(logins) = line.strip_prefix("Logins:") {
+                // Skipping teleport's internal logins like `-teleport-internal-join`
+                profile.logins = logins
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|login| !login.is_empty() && !login.starts_with('-'))
+                    .map(str::to_string)
+                    .collect();
+            }
         }
+    }
+    profiles
+}
+
+pub fn is_logged_in() -> Result<bool> {
+    Ok(profiles()?.iter().any(|profile| profile.valid))
+}
+
+/// The valid profile for the proxy, if tsh holds one
+pub fn valid_profile(proxy: &str) -> Result<Option<TshProfile>> {
+    Ok(profiles()?
+        .into_iter()
+        .find(|profile| profile.is_for(proxy) && profile.valid))
+}
+
+/// Makes sure there is a valid certificate for the proxy. An existing profile for the proxy is switched to,
+/// so only a proxy without a valid certificate requires an interactive login
+pub fn ensure_logged_in(proxy: &str, auth: Option<&String>, user: Option<&str>) -> Result<()> {
+    let success = match valid_profile(proxy)? {
+        Some(profile) if profile.active => return Ok(()),
+        Some(_) => switch(proxy)?,
+        None => login(proxy, auth, user)?,
     };
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let is_logged_in = stdout.contains("valid for");
-    Ok(is_logged_in)
+    if !success {
+        return Err(anyhow::anyhow!("Login failed"));
+    }
+    Ok(())
 }
 
-pub fn login(proxy: &str, auth: Option<&String>, user: &str) -> Result<ExitStatus> {
-    let proxy_args = format!("--proxy={}", proxy);
-    let mut args = vec!["login", proxy_args.as_str()];
+/// Makes the existing profile of the proxy the active one, without authenticating again
+pub fn switch(proxy: &str) -> Result<bool> {
+    backend::get().login(&switch_options(proxy))
+}
 
-    let user_args = format!("--user={}", user);
-    args.push(user_args.as_str());
+pub fn switch_options(proxy: &str) -> LoginOptions {
+    LoginOptions {
+        proxy: proxy.to_string(),
+        quiet: true,
+        ..Default::default()
+    }
+}
+
+/// The logins allowed by the roles of the user on the proxy
+pub fn logins(proxy: &str) -> Result<Vec<String>> {
+    Ok(valid_profile(proxy)?
+        .map(|profile| profile.logins)
+        .unwrap_or_default())
+}
+
+pub fn login(proxy: &str, auth: Option<&String>, user: Option<&str>) -> Result<bool> {
+    backend::get().login(&login_options(proxy, auth, user))
+}
+
+pub fn login_options(proxy: &str, auth: Option<&String>, user: Option<&str>) -> LoginOptions {
+    LoginOptions {
+        proxy: proxy.to_string(),
+        user: user.map(str::to_string),
+        auth: auth.cloned(),
+        ..Default::default()
+    }
+}
+
+/// Logs in again, assuming the roles of an approved access request
+pub fn login_with_request(proxy: &str, request_id: &str) -> Result<bool> {
+    backend::get().login(&LoginOptions {
+        proxy: proxy.to_string(),
+        request_id: Some(request_id.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Creates an access request for the roles without waiting for it to be reviewed
+pub fn request_create(proxy: &str, roles: &str, reason: Option<&String>) -> Result<String> {
+    output(
+        proxy,
+        &request_create_args(proxy, roles, reason),
+        "create access request",
+    )
+}
+
+/// The arguments of `tsh request create` for [`request_create`]
+pub fn request_create_args(proxy: &str, roles: &str, reason: Option<&String>) -> Vec<String> {
+    let mut args = vec![
+        "request".to_string(),
+        "create".to_string(),
+        format!("--proxy={}", proxy),
+        format!("--roles={}", roles),
+        "--nowait".to_string(),
+    ];
+    if let Some(reason) = reason {
+        args.push(format!("--reason={}", reason));
+    }
+    args
+}
 
-    let auth_args;
-    if auth.is_some() {
-        auth_args = format!("--auth={}", auth.unwrap());
-        args.push(auth_args.as_str());
+/// The access request as json, or none if tsh doesn't know it, e.g. after it expired
+pub fn request_show(proxy: &str, request_id: &str) -> Result<Option<String>> {
+    let args = vec![
+        "request".to_string(),
+        "show".to_string(),
+        format!("--proxy={}", proxy),
+        request_id.to_string(),
+        "--format=json".to_string(),
+    ];
+    let output = backend::get().output(proxy, &args)?;
+    if output.success {
+        return Ok(Some(output.stdout));
     }
+    if output.stderr.contains("not found") {
+        return Ok(None);
+    }
+    Err(anyhow::anyhow!(
+        "Could not show access request {}: {}",
+        request_id,
+        output.stderr.trim()
+    ))
+}
+
+pub fn request_ls(proxy: &str) -> Result<String> {
+    output(proxy, &request_ls_args(proxy), "list access requests")
+}
 
-    let mut process = Command::new("tsh").args(args).spawn()?;
-    process.wait().map_err(|e| anyhow::anyhow!(e))
+/// The arguments of `tsh request ls` for [`request_ls`]
+pub fn request_ls_args(proxy: &str) -> Vec<String> {
+    vec![
+        "request".to_string(),
+        "ls".to_string(),
+        format!("--proxy={}", proxy),
+        "--format=json".to_string(),
+    ]
 }
 
-pub fn logout() -> Result<ExitStatus> {
-    let mut process = Command::new("tsh").args(&["logout"]).spawn()?;
-    process.wait().map_err(|e| anyhow::anyhow!(e))
+pub fn logout() -> Result<bool> {
+    backend::get().logout()
 }
 
-pub fn ls(format: Option<&String>) -> Result<String> {
+/// The format of `tsh ls`, when none is given
+pub const LS_FORMAT: &str = "text";
+
+pub fn ls(
+    format: Option<&String>,
+    proxy: &str,
+    cluster: Option<&str>,
+    filter: &Filter,
+) -> Result<String> {
     let format = match format {
         Some(format) => format,
-        None => "text",
+        None => LS_FORMAT,
     };
-    let spinner = spinner::get_spinner();
-    spinner.set_message("Getting nodes from teleport...");
-    let output = Command::new("tsh").args(["ls", "-f", format]).output()?;
+    if filter.query.is_some() || filter.search.is_some() {
+        require(Feature::Filters, proxy)?;
+    }
+    backend::get().ls(proxy, cluster, filter, format)
+}
+
+/// Lists resources like databases as json through `tsh <subcommand> ls`
+pub fn resources(
+    subcommand: &str,
+    proxy: &str,
+    cluster: Option<&str>,
+    filter: &Filter,
+) -> Result<String> {
+    if filter.query.is_some() || filter.search.is_some() {
+        require(Feature::Filters, proxy)?;
+    }
+    backend::get().resources(subcommand, proxy, cluster, filter)
+}
+
+/// Runs tsh with the arguments in the foreground, e.g. `db connect`
+pub fn run(proxy: &str, args: &[String]) -> Result<bool> {
+    backend::get().run(proxy, args)
+}
+
+pub fn clusters(proxy: &str) -> Result<String> {
+    output(
+        proxy,
+        &clusters_args(proxy),
+        &format!("list the clusters of {}", proxy),
+    )
+}
+
+/// The arguments of `tsh clusters` for [`clusters`]
+pub fn clusters_args(proxy: &str) -> Vec<String> {
+    vec![
+        "clusters".to_string(),
+        format!("--proxy={}", proxy),
+        "--format=json".to_string(),
+    ]
+}
+
+/// Runs tsh for the proxy and returns what it printed, or what it reported as the error of the action
+fn output(proxy: &str, args: &[String], action: &str) -> Result<String> {
+    let output = backend::get().output(proxy, args)?;
+    if !output.success {
+        return Err(anyhow::anyhow!(
+            "Could not {}: {}",
+            action,
+            output.stderr.trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// The version of the tsh for the proxy, or of the default tsh without one
+pub fn version(proxy: Option<&str>) -> Result<Version> {
+    backend::get().version(proxy)
+}
 
-    spinner.finish_and_clear();
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+/// Features of tsh beam uses, which older versions of tsh lack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// `tsh ls --format=json`
+    JsonNodes,
+    /// `tsh clusters --format=json`
+    Clusters,
+    /// `tsh request create --nowait` and reading requests as json
+    AccessRequests,
+    /// `tsh ls --query` and `--search`
+    Filters,
+    /// `tsh db ls --format=json` and `tsh proxy db --tunnel`
+    Databases,
+    /// `tsh kube ls --format=json`
+    Kube,
+    /// `tsh apps ls --format=json` and `tsh proxy app`
+    Apps,
 }
 
-pub fn cmp_logged_in_proxy_with(proxy: &str) -> Result<bool> {
-    let output = Command::new("tsh").args(["status"]).output()?;
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let logged_in_proxy = stdout
-        .lines()
-        .next()
-        .unwrap()
+impl Feature {
+    pub fn min_version(&self) -> Version {
+        match self {
+            Feature::JsonNodes => Version::new(7, 0, 0),
+            Feature::Clusters => Version::new(8, 0, 0),
+            Feature::AccessRequests => Version::new(8, 0, 0),
+            Feature::Filters => Version::new(9, 0, 0),
+            Feature::Databases => Version::new(10, 0, 0),
+            Feature::Kube => Version::new(9, 0, 0),
+            Feature::Apps => Version::new(9, 0, 0),
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Feature::JsonNodes => "Listing nodes",
+            Feature::Clusters => "Listing clusters",
+            Feature::AccessRequests => "Access requests",
+            Feature::Filters => "Filtering nodes with a query or search",
+            Feature::Databases => "Database access",
+            Feature::Kube => "Kubernetes access",
+            Feature::Apps => "Application access",
+        }
+    }
+}
+
+/// Fails with a clear error, if the installed tsh is too old for the feature
+pub fn require(feature: Feature, proxy: &str) -> Result<()> {
+    let tsh = backend::tsh(Some(proxy));
+    check_version(feature, &version(Some(proxy))?, &tsh.path)
+}
+
+fn check_version(feature: Feature, version: &Version, path: &str) -> Result<()> {
+    let min_version = feature.min_version();
+    if *version < min_version {
+        return Err(anyhow::anyhow!(
+            "{} requires tsh {} or newer, but {} is tsh {}.\nPlease update tsh or point beam to a newer one with tsh_path in your profile or {}",
+            feature.describe(),
+            min_version,
+            path,
+            version,
+            crate::utils::settings::TSH_ENV
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the output of `tsh version`, e.g. `Teleport v12.1.5 git:v12.1.5-0-g4d7ea9a go1.19.6`
+pub(crate) fn parse_version(output: &str) -> Result<Version> {
+    let version = output
         .split_ascii_whitespace()
-        .nth(3)
-        .unwrap()
-        .split(':')
-        .nth(1)
-        .unwrap()
-        .split("//")
-        .nth(1)
-        .unwrap();
-    let proxy = proxy.split(':').next().unwrap();
-    Ok(proxy == logged_in_proxy)
+        .find_map(|word| word.strip_prefix('v'))
+        .with_context(|| format!("Could not parse tsh version from {}", output.trim()))?;
+    Version::parse(version).context("Could not parse tsh version")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("Teleport v12.1.5 git:v12.1.5-0-g4d7ea9a go1.19.6\n").unwrap(),
+            Version::new(12, 1, 5)
+        );
+        assert!(parse_version("command not found").is_err());
+    }
+
+    #[test]
+    fn test_check_version() {
+        assert!(check_version(Feature::JsonNodes, &Version::new(12, 1, 5), "tsh").is_ok());
+        assert!(check_version(Feature::Clusters, &Version::new(8, 0, 0), "tsh").is_ok());
+        let err =
+            check_version(Feature::AccessRequests, &Version::new(6, 2, 1), "tsh").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Access requests requires tsh 8.0.0 or newer, but"));
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output = r#"> Profile URL:        https://teleport.example.com:443
+  Logged in as:       dzefo
+  Cluster:            teleport.example.com
+  Roles:              access, editor
+  Logins:             dzefo, ubuntu, ec2-user, -teleport-internal-join
+  Kubernetes:         enabled
+  Valid until:        2022-10-19 22:03:41 +0200 CEST [valid for 11h59m0s]
+  Extensions:         permit-agent-forwarding
+
+  Profile URL:        https://staging.example.com:3080
+  Logged in as:       dzefo
+  Cluster:            staging.example.com
+  Roles:              access
+  Logins:             dzefo
+  Valid until:        2022-10-18 10:00:00 +0200 CEST [EXPIRED]
+"#;
+        let profiles = parse_status(output);
+        assert_eq!(
+            profiles[0],
+            TshProfile {
+                proxy: "teleport.example.com".to_owned(),
+                user: "dzefo".to_owned(),
+                cluster: "teleport.example.com".to_owned(),
+                active: true,
+                valid: true,
+                logins: vec![
+                    "dzefo".to_owned(),
+                    "ubuntu".to_owned(),
+                    "ec2-user".to_owned()
+                ],
+            }
+        );
+        assert!(profiles[1].is_for("staging.example.com:3080"));
+        assert!(!profiles[1].active && !profiles[1].valid);
+        assert!(parse_status("Not logged in.").is_empty());
+    }
 }
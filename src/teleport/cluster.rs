@@ -0,0 +1,53 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::teleport::cli;
+
+/// A root or leaf cluster reachable through the proxy
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cluster {
+    #[serde(rename = "cluster_name")]
+    pub name: String,
+    #[serde(rename = "cluster_type", default)]
+    pub kind: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub selected: bool,
+}
+
+impl Cluster {
+    pub fn is_online(&self) -> bool {
+        self.status.is_empty() || self.status == "online"
+    }
+}
+
+pub fn get(proxy: &str) -> Result<Vec<Cluster>> {
+    cli::require(cli::Feature::Clusters, proxy)?;
+    parse(&cli::clusters(proxy)?)
+}
+
+fn parse(clusters_json: &str) -> Result<Vec<Cluster>> {
+    serde_json::from_str(clusters_json).context("Could not read the clusters from tsh")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let clusters = parse(
+            r#"[
+  {"cluster_name": "teleport.example.com", "status": "online", "cluster_type": "root", "selected": true},
+  {"cluster_name": "leaf.example.com", "status": "offline", "cluster_type": "leaf", "selected": false}
+]"#,
+        )
+        .unwrap();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].name, "teleport.example.com");
+        assert!(clusters[0].selected && clusters[0].is_online());
+        assert_eq!(clusters[1].kind, "leaf");
+        assert!(!clusters[1].is_online());
+    }
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::teleport::cli::Feature;
+use crate::teleport::resource::{Metadata, Resource};
+
+/// A database teleport proxies, as listed by `tsh db ls --format=json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Database {
+    metadata: Metadata,
+    spec: Spec,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spec {
+    #[serde(default)]
+    pub protocol: String,
+    #[serde(default)]
+    pub uri: String,
+}
+
+impl Resource for Database {
+    const SUBCOMMAND: &'static str = "db";
+    const PLURAL: &'static str = "databases";
+    const FEATURE: Feature = Feature::Databases;
+
+    fn name(&self) -> &str {
+        &self.metadata.name
+    }
+
+    fn labels(&self) -> &HashMap<String, String> {
+        &self.metadata.labels
+    }
+
+    fn details(&self) -> Vec<String> {
+        vec![self.spec.protocol.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::teleport::resource;
+
+    #[test]
+    fn test_parse_and_skim_string() {
+        let databases: Vec<Database> = serde_json::from_str(
+            r#"[
+  {"kind": "db", "version": "v3", "metadata": {"name": "billing", "labels": {"env": "prod"}}, "spec": {"protocol": "postgres", "uri": "billing.internal:5432"}},
+  {"kind": "db", "version": "v3", "metadata": {"name": "analytics"}, "spec": {"protocol": "mysql", "uri": "analytics.internal:3306"}}
+]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            databases[0].as_node().label("env"),
+            Some(&"prod".to_owned())
+        );
+
+        let lines: Vec<Vec<String>> = resource::to_skim_string(&databases, None)
+            .lines()
+            .map(|line| line.split_whitespace().map(str::to_string).collect())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                vec!["analytics", "mysql"],
+                vec!["billing", "postgres", "env:prod"]
+            ]
+        );
+    }
+}
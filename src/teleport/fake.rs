@@ -0,0 +1,322 @@
+use anyhow::{Context, Result};
+use semver::Version;
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+use crate::teleport::backend::{LoginOptions, TeleportBackend, TshOutput};
+use crate::teleport::cli::{self, TshProfile};
+use crate::teleport::node::Filter;
+
+/// A backend keeping its state in memory instead of running tsh, which records every call.
+/// Nodes are served from fixtures in the format of `tsh ls --format=json`
+#[derive(Debug, Default)]
+pub struct FakeBackend {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    profiles: Vec<TshProfile>,
+    nodes: HashMap<String, String>,
+    /// Resources like databases by the subcommand of tsh and the proxy
+    resources: HashMap<(String, String), String>,
+    /// The logins the roles allow per proxy, which a login is granted
+    allowed_logins: HashMap<String, Vec<String>>,
+    calls: Vec<String>,
+    fail_login: bool,
+    /// Runs of tsh starting with any of these arguments fail, e.g. `db connect`
+    failing_runs: Vec<String>,
+    /// What tsh prints for calls starting with the arguments, e.g. `request ls`
+    outputs: Vec<(String, TshOutput)>,
+    version: Option<Version>,
+}
+
+/// The version of tsh the fake reports, unless another one is given
+pub const FAKE_VERSION: Version = Version::new(15, 0, 0);
+
+impl FakeBackend {
+    pub fn new() -> FakeBackend {
+        FakeBackend::default()
+    }
+
+    /// Serves the nodes for the proxy, given in the format of `tsh ls --format=json`
+    pub fn with_nodes(self, proxy: &str, nodes_json: &str) -> FakeBackend {
+        self.state
+            .lock()
+            .unwrap()
+            .nodes
+            .insert(cli::proxy_host(proxy).to_string(), nodes_json.to_string());
+        self
+    }
+
+    /// Serves resources like databases for the proxy, given in the format of `tsh <subcommand> ls --format=json`
+    pub fn with_resources(self, subcommand: &str, proxy: &str, json: &str) -> FakeBackend {
+        self.state.lock().unwrap().resources.insert(
+            (subcommand.to_string(), cli::proxy_host(proxy).to_string()),
+            json.to_string(),
+        );
+        self
+    }
+
+    pub fn with_nodes_fixture(self, proxy: &str, path: impl AsRef<Path>) -> Result<FakeBackend> {
+        let path = path.as_ref();
+        let nodes_json = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read fixture {}", path.display()))?;
+        Ok(self.with_nodes(proxy, &nodes_json))
+    }
+
+    /// The logins allowed by the teleport roles for the proxy, which `tsh status` reports after logging in
+    pub fn with_allowed_logins(self, proxy: &str, logins: &[&str]) -> FakeBackend {
+        self.state.lock().unwrap().allowed_logins.insert(
+            cli::proxy_host(proxy).to_string(),
+            logins.iter().map(|login| login.to_string()).collect(),
+        );
+        self
+    }
+
+    /// Starts out with a valid login for the proxy, which becomes the active one
+    pub fn with_login(self, proxy: &str, logins: &[&str]) -> FakeBackend {
+        let backend = self.with_allowed_logins(proxy, logins);
+        backend
+            .state
+            .lock()
+            .unwrap()
+            .log_in(proxy, logins.first().copied());
+        backend
+    }
+
+    /// Starts out with an expired login for the proxy
+    pub fn with_expired_login(self, proxy: &str) -> FakeBackend {
+        self.state.lock().unwrap().profiles.push(TshProfile {
+            proxy: cli::proxy_host(proxy).to_string(),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Makes every login without an existing valid certificate fail
+    pub fn failing_login(self) -> FakeBackend {
+        self.state.lock().unwrap().fail_login = true;
+        self
+    }
+
+    /// Makes runs of tsh starting with the arguments fail, like a failing `tsh db connect`
+    pub fn failing_run(self, args: &str) -> FakeBackend {
+        self.state
+            .lock()
+            .unwrap()
+            .failing_runs
+            .push(args.to_string());
+        self
+    }
+
+    /// Prints the output for calls of tsh starting with the arguments, like the json of `tsh request ls`
+    pub fn with_output(self, args: &str, stdout: &str) -> FakeBackend {
+        self.state.lock().unwrap().outputs.push((
+            args.to_string(),
+            TshOutput {
+                success: true,
+                stdout: stdout.to_string(),
+                ..Default::default()
+            },
+        ));
+        self
+    }
+
+    /// Makes calls of tsh starting with the arguments fail with the error, like a failing `tsh clusters`
+    pub fn failing_output(self, args: &str, stderr: &str) -> FakeBackend {
+        self.state.lock().unwrap().outputs.push((
+            args.to_string(),
+            TshOutput {
+                success: false,
+                stderr: stderr.to_string(),
+                ..Default::default()
+            },
+        ));
+        self
+    }
+
+    /// Reports the version for tsh, e.g. to test the minimum versions of features
+    pub fn with_version(self, version: Version) -> FakeBackend {
+        self.state.lock().unwrap().version = Some(version);
+        self
+    }
+
+    /// The calls made so far, written like the arguments of tsh, e.g. `login --proxy=teleport.example.com`
+    pub fn calls(&self) -> Vec<String> {
+        self.state.lock().unwrap().calls.clone()
+    }
+}
+
+impl State {
+    /// Issues a certificate for the user or, when switching, for the user of the existing login
+    fn log_in(&mut self, proxy: &str, user: Option<&str>) {
+        let proxy = cli::proxy_host(proxy).to_string();
+        let logins = self.allowed_logins.get(&proxy).cloned().unwrap_or_default();
+        let user = user.map(str::to_string).unwrap_or_else(|| {
+            self.profiles
+                .iter()
+                .find(|profile| profile.proxy == proxy)
+                .map(|profile| profile.user.clone())
+                .unwrap_or_default()
+        });
+        self.profiles.retain(|profile| profile.proxy != proxy);
+        self.profiles
+            .iter_mut()
+            .for_each(|profile| profile.active = false);
+        self.profiles.insert(
+            0,
+            TshProfile {
+                cluster: proxy.clone(),
+                proxy,
+                user,
+                active: true,
+                valid: true,
+                logins,
+            },
+        );
+    }
+}
+
+impl TeleportBackend for FakeBackend {
+    fn status(&self) -> Result<Vec<TshProfile>> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push("status".to_string());
+        Ok(state.profiles.clone())
+    }
+
+    fn login(&self, options: &LoginOptions) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let mut call = format!("login --proxy={}", options.proxy);
+        if let Some(user) = &options.user {
+            call += &format!(" --user={}", user);
+        }
+        if let Some(auth) = &options.auth {
+            call += &format!(" --auth={}", auth);
+        }
+        if let Some(request_id) = &options.request_id {
+            call += &format!(" --request-id={}", request_id);
+        }
+        state.calls.push(call);
+
+        let has_valid_login = state
+            .profiles
+            .iter()
+            .any(|profile| profile.valid && profile.is_for(&options.proxy));
+        if state.fail_login && !has_valid_login {
+            return Ok(false);
+        }
+        state.log_in(&options.proxy, options.user.as_deref());
+        Ok(true)
+    }
+
+    fn logout(&self) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push("logout".to_string());
+        state.profiles.clear();
+        Ok(true)
+    }
+
+    fn ls(
+        &self,
+        proxy: &str,
+        cluster: Option<&str>,
+        filter: &Filter,
+        format: &str,
+    ) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        let mut call = format!("ls --proxy={} -f {}", proxy, format);
+        if let Some(cluster) = cluster {
+            call += &format!(" --cluster={}", cluster);
+        }
+        for arg in filter.tsh_args() {
+            call += &format!(" {}", arg);
+        }
+        state.calls.push(call);
+        // tsh prints null for a proxy without any nodes
+        Ok(state
+            .nodes
+            .get(cli::proxy_host(proxy))
+            .cloned()
+            .unwrap_or_else(|| "null\n".to_string()))
+    }
+
+    fn resources(
+        &self,
+        subcommand: &str,
+        proxy: &str,
+        cluster: Option<&str>,
+        filter: &Filter,
+    ) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        let mut call = format!("{} ls --proxy={} --format=json", subcommand, proxy);
+        if let Some(cluster) = cluster {
+            call += &format!(" --cluster={}", cluster);
+        }
+        for arg in filter.tsh_args() {
+            call += &format!(" {}", arg);
+        }
+        state.calls.push(call);
+        Ok(state
+            .resources
+            .get(&(subcommand.to_string(), cli::proxy_host(proxy).to_string()))
+            .cloned()
+            .unwrap_or_else(|| "[]\n".to_string()))
+    }
+
+    fn run(&self, _proxy: &str, args: &[String]) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let call = args.join(" ");
+        let fails = state
+            .failing_runs
+            .iter()
+            .any(|failing| call.starts_with(failing));
+        state.calls.push(call);
+        Ok(!fails)
+    }
+
+    fn ssh(&self, _proxy: &str, args: &[String]) -> Result<bool> {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .push(format!("ssh {}", args.join(" ")));
+        Ok(true)
+    }
+
+    fn scp(&self, _proxy: &str, args: &[String]) -> Result<bool> {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .push(format!("scp {}", args.join(" ")));
+        Ok(true)
+    }
+
+    /// Calls without an output given through [`FakeBackend::with_output`] succeed without printing anything
+    fn output(&self, _proxy: &str, args: &[String]) -> Result<TshOutput> {
+        let mut state = self.state.lock().unwrap();
+        let call = args.join(" ");
+        let output = state
+            .outputs
+            .iter()
+            .find(|(args, _)| call.starts_with(args.as_str()))
+            .map(|(_, output)| output.clone())
+            .unwrap_or(TshOutput {
+                success: true,
+                ..Default::default()
+            });
+        state.calls.push(call);
+        Ok(output)
+    }
+
+    /// Not recorded as a call, as beam checks the version before using newer features
+    fn version(&self, _proxy: Option<&str>) -> Result<Version> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .version
+            .clone()
+            .unwrap_or(FAKE_VERSION))
+    }
+}
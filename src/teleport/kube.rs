@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::teleport::cli::Feature;
+use crate::teleport::resource::Resource;
+
+/// A kubernetes cluster teleport gives access to, as listed by `tsh kube ls --format=json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KubeCluster {
+    #[serde(rename = "kube_cluster_name")]
+    name: String,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+impl Resource for KubeCluster {
+    const SUBCOMMAND: &'static str = "kube";
+    const PLURAL: &'static str = "kubernetes clusters";
+    const FEATURE: Feature = Feature::Kube;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let clusters: Vec<KubeCluster> = serde_json::from_str(
+            r#"[
+  {"kube_cluster_name": "prod-eu", "labels": {"env": "prod", "region": "eu-west-1"}, "selected": true},
+  {"kube_cluster_name": "staging", "selected": false}
+]"#,
+        )
+        .unwrap();
+        assert_eq!(clusters[0].name(), "prod-eu");
+        assert_eq!(
+            clusters[0].as_node().label("region"),
+            Some(&"eu-west-1".to_owned())
+        );
+        assert!(clusters[1].labels().is_empty());
+    }
+}
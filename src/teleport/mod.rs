@@ -1,2 +1,10 @@
+pub mod app;
+pub mod backend;
 pub mod cli;
+pub mod cluster;
+pub mod database;
+pub mod fake;
+pub mod kube;
 pub mod node;
+pub mod request;
+pub mod resource;
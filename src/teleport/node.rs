@@ -1,10 +1,18 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use colored::Colorize;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use crate::teleport::cli;
-use crate::utils::profiles::DEFAULT_PROFILE;
+use crate::teleport::backend::{self, TshCommand};
+use crate::teleport::cli::Feature;
+use crate::teleport::{cli, cluster};
+use crate::utils::{config::Config, paths, profile::Profile, settings::DEFAULT_CACHE_TTL, spinner};
 
 pub trait SkimString {
     fn to_skim_string(self, label_whitelist: Option<Vec<String>>) -> String;
@@ -14,6 +22,14 @@ pub trait SkimString {
 pub struct Node {
     metadata: Metadata,
     pub spec: Spec,
+    /// The cluster the node was listed from, if it was listed for a specific cluster
+    #[serde(skip)]
+    pub cluster: Option<String>,
+    /// The profile and its proxy the node was listed with, when listing the nodes of several proxies
+    #[serde(skip)]
+    pub profile: Option<String>,
+    #[serde(skip)]
+    pub proxy: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +45,38 @@ pub struct Spec {
     pub hostname: String,
 }
 
+impl Node {
+    pub fn new(hostname: &str, labels: HashMap<String, String>) -> Node {
+        Node {
+            metadata: Metadata {
+                name: hostname.to_string(),
+                labels,
+                expires: String::new(),
+                id: 0,
+            },
+            spec: Spec {
+                hostname: hostname.to_string(),
+            },
+            cluster: None,
+            profile: None,
+            proxy: None,
+        }
+    }
+
+    pub fn label(&self, key: &str) -> Option<&String> {
+        self.metadata.labels.get(key)
+    }
+
+    pub fn has_labels(&self, labels: &BTreeMap<String, String>) -> bool {
+        labels
+            .iter()
+            .all(|(key, value)| self.label(key) == Some(value))
+    }
+}
+
+/// A column of the picker, which is only shown if some nodes have a value for it
+type Tag = fn(&Node) -> Option<&String>;
+
 impl SkimString for Vec<Node> {
     fn to_skim_string(self, label_whitelist: Option<Vec<String>>) -> String {
         let mut skim_string = String::new();
@@ -39,6 +87,22 @@ impl SkimString for Vec<Node> {
             .map(|node| node.spec.hostname.len())
             .max()
             .unwrap_or(0);
+        // Nodes from several proxies or clusters get a column for each after the hostname
+        let tags: [Tag; 3] = [
+            |node| node.profile.as_ref(),
+            |node| node.proxy.as_ref(),
+            |node| node.cluster.as_ref(),
+        ];
+        let tag_widths: Vec<(Tag, usize)> = tags
+            .into_iter()
+            .filter_map(|tag| {
+                let width = self
+                    .iter()
+                    .filter_map(|node| tag(node).map(String::len))
+                    .max()?;
+                Some((tag, width))
+            })
+            .collect();
 
         // sort nodes by hostname reverse
         let mut nodes = self;
@@ -54,9 +118,20 @@ impl SkimString for Vec<Node> {
                 }
             }
 
+            let mut tag_string = String::new();
+            for (tag, width) in &tag_widths {
+                tag_string += format!(
+                    "{:<width$} ",
+                    tag(&node).map(String::as_str).unwrap_or("-"),
+                    width = width + 5
+                )
+                .as_str();
+            }
+
             skim_string += format!(
-                "{:<width$} {}\n",
+                "{:<width$} {}{}\n",
                 node.spec.hostname,
+                tag_string,
                 label_string,
                 width = longest_hostname_length + 15
             )
@@ -67,54 +142,293 @@ impl SkimString for Vec<Node> {
     }
 }
 
-pub fn get(use_cache: bool, proxy: &str) -> Result<Vec<Node>> {
-    let cache_file = home::home_dir()
-        .unwrap()
-        .join(format!(".beam/cache/{}.json", proxy));
+/// Restricts the nodes teleport lists, so only the matching nodes are transferred and cached
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Filter {
+    /// A teleport predicate, e.g. `labels["env"] == "prod"`
+    pub query: Option<String>,
+    /// Keywords to search the hostname and labels for, separated by commas
+    pub search: Option<String>,
+    pub labels: Option<BTreeMap<String, String>>,
+}
 
-    let is_cache_file_old = if cache_file.exists() {
-        let metadata = cache_file.metadata()?;
-        let ttl = DEFAULT_PROFILE.config.cache_ttl.unwrap_or(60 * 60 * 24);
-        metadata.modified()?.elapsed()? > Duration::from_secs(ttl)
-    } else {
-        true
-    };
+impl Filter {
+    pub fn for_config(config: &Config) -> Filter {
+        Filter {
+            query: config.query.clone(),
+            search: config.search.clone(),
+            labels: config.label_filter.clone(),
+        }
+    }
 
-    let nodes = if !std::path::Path::new(&cache_file).exists() || is_cache_file_old || !use_cache {
-        get_from_tsh(proxy)?
+    pub fn is_empty(&self) -> bool {
+        self.query.is_none()
+            && self.search.is_none()
+            && self.labels.as_ref().is_none_or(BTreeMap::is_empty)
+    }
+
+    /// The arguments of `tsh ls` applying the filter
+    pub fn tsh_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(query) = &self.query {
+            args.push(format!("--query={}", query));
+        }
+        if let Some(search) = &self.search {
+            args.push(format!("--search={}", search));
+        }
+        if let Some(labels) = self.labels.as_ref().filter(|labels| !labels.is_empty()) {
+            args.push(
+                labels
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .join(","),
+            );
+        }
+        args
+    }
+
+    /// Identifies the filter in the name of the cache file. It hashes the arguments of `tsh ls`, as they
+    /// describe the filter canonically, with FNV-1a, as it is stable across runs and versions
+    fn cache_key(&self) -> String {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in self.tsh_args().join("\0").bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        format!("{:016x}", hash)
+    }
+}
+
+/// What to list the nodes of a proxy for and how
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listing {
+    /// The trusted (leaf) cluster to list the nodes of, instead of the root cluster
+    pub cluster: Option<String>,
+    pub filter: Filter,
+}
+
+impl Listing {
+    pub fn for_profile(profile: &Profile) -> Listing {
+        Listing {
+            cluster: profile.config.cluster.clone(),
+            filter: Filter::for_config(&profile.config),
+        }
+    }
+
+    pub fn cluster(&self) -> Option<&str> {
+        self.cluster.as_deref()
+    }
+}
+
+pub fn get(use_cache: bool, proxy: &str, listing: &Listing, ttl: u64) -> Result<Vec<Node>> {
+    let mut nodes = if use_cache && is_cache_fresh(proxy, listing, ttl)? {
+        get_from_cache(proxy, listing)?
     } else {
-        get_from_cache(proxy)?
+        let spinner = spinner::get_spinner();
+        spinner.set_message("Getting nodes from teleport...");
+        let nodes = get_from_tsh(proxy, listing);
+        spinner.finish_and_clear();
+        nodes?
     };
+    for node in nodes.iter_mut() {
+        node.cluster = listing.cluster.clone();
+    }
 
     Ok(nodes)
 }
 
-fn get_from_tsh(proxy: &str) -> Result<Vec<Node>> {
-    let tsh_json = cli::ls(Some(&"json".to_string()))?;
+fn is_cache_fresh(proxy: &str, listing: &Listing, ttl: u64) -> Result<bool> {
+    is_fresh(&cache_file(proxy, listing), ttl)
+}
+
+/// Whether the cache file exists and is younger than the ttl in seconds
+pub(crate) fn is_fresh(cache_file: &Path, ttl: u64) -> Result<bool> {
+    if !cache_file.exists() {
+        return Ok(false);
+    }
+    Ok(cache_file.metadata()?.modified()?.elapsed()? <= Duration::from_secs(ttl))
+}
+
+/// Lists the nodes of every distinct proxy of the profiles concurrently, tagged with the profile and proxy.
+/// Proxies without a valid login only contribute their cached nodes, however old, and are skipped without a cache
+pub async fn get_all_proxies(use_cache: bool, profiles: Vec<Profile>) -> Result<Vec<Node>> {
+    let tsh_profiles = cli::profiles()?;
+    let spinner = spinner::get_spinner();
+    spinner.set_message(format!("Getting nodes from {} proxies...", profiles.len()));
+
+    let tasks: Vec<_> = profiles
+        .into_iter()
+        .filter_map(|profile| {
+            let proxy = profile.config.proxy.clone()?;
+            backend::add_tsh(&proxy, TshCommand::for_profile(&profile));
+            let logged_in = tsh_profiles
+                .iter()
+                .any(|tsh_profile| tsh_profile.valid && tsh_profile.is_for(&proxy));
+            Some(tokio::task::spawn_blocking(move || {
+                let listing = Listing::for_profile(&profile);
+                let ttl = profile.config.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL);
+                let nodes = if !logged_in || (use_cache && is_cache_fresh(&proxy, &listing, ttl)?) {
+                    get_from_cache(&proxy, &listing).with_context(|| {
+                        format!("not logged in with {} and no nodes are cached", proxy)
+                    })?
+                } else {
+                    get_from_tsh(&proxy, &listing)?
+                };
+                Ok::<_, anyhow::Error>((profile, proxy, nodes))
+            }))
+        })
+        .collect();
+
+    let mut nodes = vec![];
+    let mut problems = vec![];
+    for task in tasks {
+        match task.await? {
+            Ok((profile, proxy, proxy_nodes)) => {
+                nodes.extend(proxy_nodes.into_iter().map(|mut node| {
+                    node.profile = Some(profile.name.clone());
+                    node.proxy = Some(proxy.clone());
+                    node.cluster = profile.config.cluster.clone();
+                    node
+                }))
+            }
+            Err(err) => problems.push(err),
+        }
+    }
+    spinner.finish_and_clear();
+
+    for problem in problems {
+        eprintln!("{} Skipping a proxy: {:#}", "Warning:".yellow(), problem);
+    }
+    Ok(nodes)
+}
+
+/// Lists the nodes of every reachable cluster behind the proxy. Clusters without any nodes are skipped
+pub fn get_all_clusters(
+    use_cache: bool,
+    proxy: &str,
+    listing: &Listing,
+    ttl: u64,
+) -> Result<Vec<Node>> {
+    let mut nodes = vec![];
+    for cluster in cluster::get(proxy)? {
+        if !cluster.is_online() {
+            continue;
+        }
+        let listing = Listing {
+            cluster: Some(cluster.name),
+            ..listing.clone()
+        };
+        match get(use_cache, proxy, &listing, ttl) {
+            Ok(cluster_nodes) => nodes.extend(cluster_nodes),
+            Err(err) if err.is::<NoNodesError>() => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(nodes)
+}
+
+/// Each cluster is cached separately, the nodes listed without a cluster in `<proxy>.json`
+fn cache_file(proxy: &str, listing: &Listing) -> PathBuf {
+    paths::cache_dir().join(cache_file_name(proxy, listing))
+}
+
+pub(crate) fn cache_file_name(proxy: &str, listing: &Listing) -> String {
+    let mut file_name = match listing.cluster() {
+        Some(cluster) => format!("{}.{}", proxy, cluster),
+        None => proxy.to_string(),
+    };
+    // Nodes listed with a filter are cached separately from each other and from all nodes
+    if !listing.filter.is_empty() {
+        file_name += &format!(".{}", listing.filter.cache_key());
+    }
+    file_name + ".json"
+}
+
+#[derive(Debug)]
+struct NoNodesError;
+
+impl fmt::Display for NoNodesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "This proxy does not seem to have any nodes")
+    }
+}
+
+impl std::error::Error for NoNodesError {}
+
+fn get_from_tsh(proxy: &str, listing: &Listing) -> Result<Vec<Node>> {
+    cli::require(Feature::JsonNodes, proxy)?;
+    let tsh_json = cli::ls(
+        Some(&"json".to_string()),
+        proxy,
+        listing.cluster(),
+        &listing.filter,
+    )?;
     if tsh_json == "null\n" {
-        return Err(anyhow::anyhow!(
-            "This proxy does not seem to have any nodes"
-        ));
+        return Err(NoNodesError.into());
     }
     let tsh_nodes: Vec<Node> = serde_json::from_str(&tsh_json)?;
-    write_to_cache(tsh_json, proxy)?;
+    write_to_cache(tsh_json, proxy, listing)?;
 
     Ok(tsh_nodes)
 }
 
-fn get_from_cache(proxy: &str) -> Result<Vec<Node>> {
-    let cache_path = home::home_dir()
-        .unwrap()
-        .join(format!(".beam/cache/{}.json", proxy));
-    let cache_json = std::fs::read_to_string(cache_path)?;
+fn get_from_cache(proxy: &str, listing: &Listing) -> Result<Vec<Node>> {
+    let cache_json = std::fs::read_to_string(cache_file(proxy, listing))?;
     let cached_nodes: Vec<Node> = serde_json::from_str(&cache_json)?;
     Ok(cached_nodes)
 }
 
-pub fn write_to_cache(nodes_json: String, proxy: &str) -> Result<()> {
-    let cache_dir = home::home_dir().unwrap().join(".beam/cache");
-    std::fs::create_dir_all(&cache_dir)?;
-    let cache_file = cache_dir.join(format!("{}.json", proxy));
-    std::fs::write(cache_file, nodes_json)?;
+pub fn write_to_cache(nodes_json: String, proxy: &str, listing: &Listing) -> Result<()> {
+    std::fs::create_dir_all(paths::cache_dir())?;
+    std::fs::write(cache_file(proxy, listing), nodes_json)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_cache_file() {
+        let listing = Listing {
+            cluster: Some("leaf".to_owned()),
+            ..Default::default()
+        };
+        let filtered = Listing {
+            filter: Filter {
+                search: Some("web".to_owned()),
+                labels: Some(BTreeMap::from([("env".to_owned(), "prod".to_owned())])),
+                ..Default::default()
+            },
+            ..listing.clone()
+        };
+        assert!(cache_file("teleport.example.com", &listing)
+            .ends_with("teleport.example.com.leaf.json"));
+        assert_ne!(
+            cache_file("teleport.example.com", &listing),
+            cache_file("teleport.example.com", &filtered)
+        );
+        // The key must not change between versions, so cached nodes stay valid
+        assert_eq!(filtered.filter.cache_key(), "d049b74dd498512b");
+        assert_eq!(filtered.filter.tsh_args(), vec!["--search=web", "env=prod"]);
+    }
+
+    #[test]
+    fn test_to_skim_string_cluster_column() {
+        let mut root = Node::new("web-1", HashMap::new());
+        root.cluster = Some("root".to_owned());
+        let mut leaf = Node::new("web-1", HashMap::new());
+        leaf.cluster = Some("leaf.example.com".to_owned());
+
+        let items = vec![root, leaf].to_skim_string(None);
+        let columns: Vec<Vec<&str>> = items
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert!(columns.contains(&vec!["web-1", "root"]));
+        assert!(columns.contains(&vec!["web-1", "leaf.example.com"]));
+
+        let items = vec![Node::new("web-1", HashMap::new())].to_skim_string(None);
+        assert_eq!(items.split_whitespace().collect::<Vec<_>>(), vec!["web-1"]);
+    }
+}
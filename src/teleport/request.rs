@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use crate::teleport::cli;
+use crate::utils::spinner;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessRequest {
+    metadata: Metadata,
+    pub spec: Spec,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Metadata {
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Spec {
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub state: serde_json::Value,
+    #[serde(default)]
+    pub request_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Pending,
+    Approved,
+    Denied,
+    Unknown,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Pending => write!(f, "PENDING"),
+            State::Approved => write!(f, "APPROVED"),
+            State::Denied => write!(f, "DENIED"),
+            State::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+impl AccessRequest {
+    pub fn id(&self) -> &str {
+        &self.metadata.name
+    }
+
+    /// Depending on the version, tsh reports the state as a number or as a name
+    pub fn state(&self) -> State {
+        match &self.spec.state {
+            serde_json::Value::Number(number) => match number.as_u64() {
+                Some(1) => State::Pending,
+                Some(2) => State::Approved,
+                Some(3) => State::Denied,
+                _ => State::Unknown,
+            },
+            serde_json::Value::String(name) => match name.to_uppercase().as_str() {
+                "PENDING" => State::Pending,
+                "APPROVED" => State::Approved,
+                "DENIED" => State::Denied,
+                _ => State::Unknown,
+            },
+            _ => State::Unknown,
+        }
+    }
+}
+
+/// Creates an access request and returns its id
+pub fn create(proxy: &str, roles: &str, reason: Option<&String>) -> Result<String> {
+    cli::require(cli::Feature::AccessRequests, proxy)?;
+    parse_request_id(&cli::request_create(proxy, roles, reason)?)
+}
+
+/// The access request, or none if tsh doesn't know it anymore, e.g. after it expired
+pub fn get(proxy: &str, request_id: &str) -> Result<Option<AccessRequest>> {
+    let request_json = match cli::request_show(proxy, request_id)? {
+        Some(request_json) => request_json,
+        None => return Ok(None),
+    };
+    serde_json::from_str(&request_json)
+        .map(Some)
+        .with_context(|| format!("Could not read access request {}", request_id))
+}
+
+pub fn list(proxy: &str) -> Result<Vec<AccessRequest>> {
+    cli::require(cli::Feature::AccessRequests, proxy)?;
+    let requests_json = cli::request_ls(proxy)?;
+    if requests_json.trim().is_empty() || requests_json.trim() == "null" {
+        return Ok(vec![]);
+    }
+    serde_json::from_str(&requests_json).context("Could not read access requests")
+}
+
+/// How waiting for the review of an access request ended
+#[derive(Debug)]
+pub enum Review {
+    Approved(AccessRequest),
+    Denied,
+    /// The request is gone before it was reviewed
+    Expired,
+}
+
+/// Waits until the access request has been reviewed, failing if it is still pending after the timeout
+pub fn wait_for_approval(proxy: &str, request_id: &str, timeout: Duration) -> Result<Review> {
+    let spinner = spinner::get_spinner();
+    spinner.set_message(format!(
+        "Waiting for access request {} to be approved...",
+        request_id
+    ));
+    let review = wait(
+        || get(proxy, request_id),
+        request_id,
+        timeout,
+        POLL_INTERVAL,
+    );
+    spinner.finish_and_clear();
+    review
+}
+
+fn wait(
+    mut get: impl FnMut() -> Result<Option<AccessRequest>>,
+    request_id: &str,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<Review> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let request = match get()? {
+            Some(request) => request,
+            None => return Ok(Review::Expired),
+        };
+        match request.state() {
+            State::Approved => return Ok(Review::Approved(request)),
+            State::Denied => return Ok(Review::Denied),
+            State::Pending if Instant::now() >= deadline => {
+                return Err(anyhow!(
+                    "Access request {} was not reviewed within {} seconds. It stays pending, see beam request ls",
+                    request_id,
+                    timeout.as_secs()
+                ))
+            }
+            State::Pending => thread::sleep(interval),
+            State::Unknown => {
+                return Err(anyhow!(
+                    "Access request {} is in an unknown state",
+                    request_id
+                ))
+            }
+        }
+    }
+}
+
+/// Parses the `Request ID:` line in the output of `tsh request create`
+fn parse_request_id(output: &str) -> Result<String> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Request ID:"))
+        .map(|id| id.trim().to_string())
+        .with_context(|| format!("Could not find the request id in {}", output.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_id() {
+        let output = r#"Creating request...
+Request ID: 0d5a4a3c-9e2a-4a0e-9d5b-3f1c2b7a8e61
+Username:   dzefo
+Roles:      dba
+Reason:     [none]
+Status:     PENDING
+"#;
+        assert_eq!(
+            parse_request_id(output).unwrap(),
+            "0d5a4a3c-9e2a-4a0e-9d5b-3f1c2b7a8e61"
+        );
+        assert!(parse_request_id("").is_err());
+    }
+
+    #[test]
+    fn test_request_state() {
+        let requests: Vec<AccessRequest> = serde_json::from_str(
+            r#"[
+  {"kind": "access_request", "metadata": {"name": "a"}, "spec": {"roles": ["dba"], "state": 1}},
+  {"kind": "access_request", "metadata": {"name": "b"}, "spec": {"roles": ["dba"], "state": "APPROVED"}},
+  {"kind": "access_request", "metadata": {"name": "c"}, "spec": {"roles": ["dba"], "state": 3}}
+]"#,
+        )
+        .unwrap();
+        assert_eq!(requests[0].id(), "a");
+        assert_eq!(requests[0].state(), State::Pending);
+        assert_eq!(requests[1].state(), State::Approved);
+        assert_eq!(requests[2].state(), State::Denied);
+    }
+
+    #[test]
+    fn test_wait() {
+        let request = |state: &str| {
+            serde_json::from_str::<AccessRequest>(&format!(
+                r#"{{"metadata": {{"name": "a"}}, "spec": {{"state": "{}"}}}}"#,
+                state
+            ))
+            .map(Some)
+            .map_err(anyhow::Error::from)
+        };
+
+        let mut states = vec!["APPROVED", "PENDING"];
+        let review = wait(
+            || request(states.pop().unwrap()),
+            "a",
+            Duration::from_secs(60),
+            Duration::ZERO,
+        );
+        assert!(matches!(review.unwrap(), Review::Approved(_)));
+
+        let review = wait(|| request("DENIED"), "a", Duration::ZERO, Duration::ZERO);
+        assert!(matches!(review.unwrap(), Review::Denied));
+        let review = wait(|| Ok(None), "a", Duration::ZERO, Duration::ZERO);
+        assert!(matches!(review.unwrap(), Review::Expired));
+
+        let err = wait(|| request("PENDING"), "a", Duration::ZERO, Duration::ZERO).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Access request a was not reviewed"));
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Context, Result};
+use itertools::Itertools;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::cli::Beam;
+use crate::teleport::cli::{self, Feature};
+use crate::teleport::node::{self, Listing, Node};
+use crate::utils::settings::{Settings, Source};
+use crate::utils::{paths, skim, spinner};
+
+/// A kind of teleport resource besides nodes, e.g. databases, listed through `tsh <subcommand> ls --format=json`
+pub trait Resource: Serialize + DeserializeOwned + Clone {
+    /// The subcommand of tsh for the resource, e.g. `db`
+    const SUBCOMMAND: &'static str;
+    /// The resource in messages, e.g. `databases`
+    const PLURAL: &'static str;
+    /// The feature of tsh needed to list the resources
+    const FEATURE: Feature;
+
+    fn name(&self) -> &str;
+    fn labels(&self) -> &HashMap<String, String>;
+
+    /// Columns shown between the name and the labels in the picker, e.g. the protocol of a database
+    fn details(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// A node named and labeled like the resource, to match profiles against
+    fn as_node(&self) -> Node {
+        Node::new(self.name(), self.labels().clone())
+    }
+}
+
+/// The metadata teleport reports for databases and apps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+/// Lists the resources behind the proxy. They are cached like nodes, in a directory for each kind of resource
+pub fn get<R: Resource>(
+    use_cache: bool,
+    proxy: &str,
+    listing: &Listing,
+    ttl: u64,
+) -> Result<Vec<R>> {
+    let cache_file = cache_file::<R>(proxy, listing);
+    if use_cache && node::is_fresh(&cache_file, ttl)? {
+        let cache_json = fs::read_to_string(&cache_file)?;
+        return Ok(serde_json::from_str(&cache_json)?);
+    }
+
+    cli::require(R::FEATURE, proxy)?;
+    let spinner = spinner::get_spinner();
+    spinner.set_message(format!("Getting {} from teleport...", R::PLURAL));
+    let tsh_json = cli::resources(R::SUBCOMMAND, proxy, listing.cluster(), &listing.filter);
+    spinner.finish_and_clear();
+    let tsh_json = tsh_json?;
+
+    let resources: Vec<R> = match tsh_json.trim() {
+        "" | "null" => vec![],
+        tsh_json => serde_json::from_str(tsh_json)
+            .with_context(|| format!("Could not read the {} from tsh", R::PLURAL))?,
+    };
+    if resources.is_empty() {
+        return Err(anyhow!("There are no {} behind {}", R::PLURAL, proxy));
+    }
+    fs::create_dir_all(cache_file.parent().unwrap())?;
+    fs::write(&cache_file, tsh_json)?;
+
+    Ok(resources)
+}
+
+fn cache_file<R: Resource>(proxy: &str, listing: &Listing) -> PathBuf {
+    paths::cache_dir()
+        .join(R::SUBCOMMAND)
+        .join(node::cache_file_name(proxy, listing))
+}
+
+pub fn to_skim_string<R: Resource>(
+    resources: &[R],
+    label_whitelist: Option<Vec<String>>,
+) -> String {
+    let name_width = resources
+        .iter()
+        .map(|resource| resource.name().len())
+        .max()
+        .unwrap_or(0);
+    let details: Vec<Vec<String>> = resources.iter().map(Resource::details).collect();
+    let detail_widths: Vec<usize> = (0..details.first().map(Vec::len).unwrap_or(0))
+        .map(|column| {
+            details
+                .iter()
+                .map(|row| row[column].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let label_whitelist = label_whitelist.unwrap_or_default();
+
+    let mut skim_string = String::new();
+    for (resource, details) in resources
+        .iter()
+        .zip(details)
+        .sorted_by_key(|(r, _)| r.name())
+    {
+        let mut line = format!("{:<width$} ", resource.name(), width = name_width + 15);
+        for (detail, width) in details.iter().zip(&detail_widths) {
+            line += &format!("{:<width$} ", detail, width = width + 5);
+        }
+        let labels = resource.labels();
+        for key in labels.keys().sorted() {
+            if label_whitelist.is_empty() || label_whitelist.contains(key) {
+                line += &format!("{}:{} ", key, labels[key]);
+            }
+        }
+        skim_string += line.trim_end();
+        skim_string += "\n";
+    }
+    skim_string
+}
+
+/// A resource chosen by name or in the picker, with the settings for it
+pub struct Chosen<R> {
+    pub resource: R,
+    pub settings: Settings,
+    /// The proxy the resource was listed through, which is kept even if the matching profile has another one
+    pub proxy: String,
+    pub listing: Listing,
+}
+
+/// Lists the resources behind the proxy and chooses the one with the name or, without a name, in the picker.
+/// Like for nodes, an explicitly selected profile is kept, otherwise a profile matching the resource is used.
+/// Returns `None`, if the picker was aborted
+pub fn choose<R: Resource>(beam: &Beam, name: Option<&str>) -> Result<Option<Chosen<R>>> {
+    if name.is_none() {
+        beam.ensure_interactive(&format!("Selecting one of the {}", R::PLURAL))?;
+    }
+    let settings = Settings::get(beam)?;
+    let proxy = settings.proxy()?.to_owned();
+    cli::ensure_logged_in(&proxy, settings.auth(), settings.teleport_user().as_deref())?;
+
+    let listing = settings.listing();
+    let resources: Vec<R> = get(
+        !beam.clear_cache,
+        &proxy,
+        &listing,
+        settings.cache_ttl.value,
+    )?;
+    let label_whitelist = settings.profile.value.config.label_whitelist.clone();
+    let resource = match find_or_select(name, &resources, label_whitelist)? {
+        Some(resource) => resource,
+        None => return Ok(None),
+    };
+
+    let settings = match settings.profile.source {
+        Source::Flag | Source::Env(_) => settings,
+        _ => Settings::get_for_node(beam, &resource.as_node())?,
+    };
+    Ok(Some(Chosen {
+        resource,
+        settings,
+        proxy,
+        listing,
+    }))
+}
+
+/// The resource with the name or, without a name, the one chosen in the picker
+fn find_or_select<R: Resource>(
+    name: Option<&str>,
+    resources: &[R],
+    label_whitelist: Option<Vec<String>>,
+) -> Result<Option<R>> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => match skim::skim(to_skim_string(resources, label_whitelist), &[])? {
+            Some(selection) => selection
+                .item
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            None => return Ok(None),
+        },
+    };
+    resources
+        .iter()
+        .find(|resource| resource.name() == name)
+        .cloned()
+        .map(Some)
+        .with_context(|| format!("{} not found in teleport", name))
+}
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap as Map;
+
+use crate::utils::login::LoginRule;
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
 pub struct Config {
@@ -11,4 +14,61 @@ pub struct Config {
     pub listen_port: Option<u16>,
     pub remote_port: Option<u16>,
     pub remote_host: Option<String>,
+    /// Only nodes having all of these labels are listed by teleport and shown in the picker
+    pub label_filter: Option<Map<String, String>>,
+    /// A teleport predicate the listed nodes must match, e.g. `labels["env"] == "prod"`
+    pub query: Option<String>,
+    /// Keywords the hostname or labels of the listed nodes must contain, separated by commas
+    pub search: Option<String>,
+    /// Logins for specific nodes, the first matching rule wins over the username
+    pub login_rules: Option<Vec<LoginRule>>,
+    /// The role to request through `beam request` for hosts matching this profile
+    pub request_role: Option<String>,
+    /// The trusted (leaf) cluster to list and connect to nodes of
+    pub cluster: Option<String>,
+    /// The tsh executable to use, e.g. to keep an older tsh for an older cluster
+    pub tsh_path: Option<String>,
+    /// Arguments passed to every call of tsh, e.g. `--insecure`
+    pub tsh_args: Option<Vec<String>>,
+    /// The database user to log in as through `beam db`
+    pub db_user: Option<String>,
+    /// The name of the database to connect to through `beam db`
+    pub db_name: Option<String>,
+    /// Starts a local tunnel on this port through `beam db`, instead of launching the database client
+    pub db_tunnel_port: Option<u16>,
+    /// The namespace to switch to after logging in to a kubernetes cluster through `beam kube`
+    pub kube_namespace: Option<String>,
+    /// Starts a local proxy on this port through `beam app`, instead of printing the URL and certificates
+    pub app_port: Option<u16>,
+}
+
+impl Config {
+    /// Overrides every value of this config, which is set in `overrides`
+    pub fn merge(&mut self, overrides: &Config) {
+        let overrides = overrides.clone();
+        self.username = overrides.username.or(self.username.take());
+        self.proxy = overrides.proxy.or(self.proxy.take());
+        self.auth = overrides.auth.or(self.auth.take());
+        self.cache_ttl = overrides.cache_ttl.or(self.cache_ttl.take());
+        self.label_whitelist = overrides.label_whitelist.or(self.label_whitelist.take());
+        self.enable_port_forwarding = overrides
+            .enable_port_forwarding
+            .or(self.enable_port_forwarding.take());
+        self.listen_port = overrides.listen_port.or(self.listen_port.take());
+        self.remote_port = overrides.remote_port.or(self.remote_port.take());
+        self.remote_host = overrides.remote_host.or(self.remote_host.take());
+        self.label_filter = overrides.label_filter.or(self.label_filter.take());
+        self.query = overrides.query.or(self.query.take());
+        self.search = overrides.search.or(self.search.take());
+        self.login_rules = overrides.login_rules.or(self.login_rules.take());
+        self.request_role = overrides.request_role.or(self.request_role.take());
+        self.cluster = overrides.cluster.or(self.cluster.take());
+        self.tsh_path = overrides.tsh_path.or(self.tsh_path.take());
+        self.tsh_args = overrides.tsh_args.or(self.tsh_args.take());
+        self.db_user = overrides.db_user.or(self.db_user.take());
+        self.db_name = overrides.db_name.or(self.db_name.take());
+        self.db_tunnel_port = overrides.db_tunnel_port.or(self.db_tunnel_port.take());
+        self.kube_namespace = overrides.kube_namespace.or(self.kube_namespace.take());
+        self.app_port = overrides.app_port.or(self.app_port.take());
+    }
 }
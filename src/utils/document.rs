@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use toml_edit::{Document, Item, Table};
+
+/// Applies the contents of `updated` to the TOML document `original`, while keeping its comments and formatting.
+/// Only the keys, which changed, are rewritten. Keys missing in `updated` are removed and new ones are appended
+pub fn update(original: &str, updated: &str) -> Result<String> {
+    let mut document: Document = original
+        .parse()
+        .context("Error while parsing the existing file")?;
+    let updated: Document = updated.parse()?;
+
+    let mut position = last_position(document.as_table());
+    merge_table(document.as_table_mut(), updated.as_table(), &mut position);
+    Ok(document.to_string())
+}
+
+fn merge_table(current: &mut Table, updated: &Table, position: &mut usize) {
+    current.retain(|key, _| updated.contains_key(key));
+
+    for (key, updated_item) in updated.iter() {
+        match current.get_mut(key) {
+            Some(current_item) if same_value(current_item, updated_item) => {}
+            Some(Item::Table(current_table)) if updated_item.is_table() => {
+                merge_table(current_table, updated_item.as_table().unwrap(), position)
+            }
+            Some(Item::Value(current_value)) if updated_item.is_value() => {
+                // Keeps comments after the value
+                let decor = current_value.decor().clone();
+                *current_value = updated_item.as_value().unwrap().clone();
+                *current_value.decor_mut() = decor;
+            }
+            _ => {
+                let mut item = updated_item.clone();
+                if let Item::Table(table) = &mut item {
+                    place_after(table, position);
+                }
+                current.insert(key, item);
+            }
+        }
+    }
+}
+
+/// Compares the items by their values, ignoring formatting
+fn same_value(a: &Item, b: &Item) -> bool {
+    match (to_value(a), to_value(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn to_value(item: &Item) -> Option<toml::Value> {
+    let mut document = Document::new();
+    document.insert("value", item.clone());
+    let value: toml::Value = toml::from_str(&document.to_string()).ok()?;
+    value.get("value").cloned()
+}
+
+fn last_position(table: &Table) -> usize {
+    table
+        .iter()
+        .filter_map(|(_, item)| item.as_table())
+        .map(|table| table.position().unwrap_or(0).max(last_position(table)))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Moves an added table and its sub-tables to the end of the document
+fn place_after(table: &mut Table, position: &mut usize) {
+    *position += 1;
+    table.set_position(*position);
+    for (_, item) in table.iter_mut() {
+        if let Item::Table(table) = item {
+            place_after(table, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_preserves_comments() {
+        let original = r#"# Managed by hand, see OPS-123
+schema_version = 1
+
+# Production, keep the forward for the billing db
+[profile.prod]
+default = true
+proxy   = "teleport.example.com" # the old proxy is gone
+username = "dzefo"
+
+[profile.staging]
+proxy = "staging.example.com"
+"#;
+        let updated = r#"schema_version = 1
+
+[profile.dev]
+proxy = "dev.example.com"
+
+[profile.prod]
+default = true
+proxy = "teleport.example.com"
+username = "michael"
+"#;
+
+        assert_eq!(
+            update(original, updated).unwrap(),
+            r#"# Managed by hand, see OPS-123
+schema_version = 1
+
+# Production, keep the forward for the billing db
+[profile.prod]
+default = true
+proxy   = "teleport.example.com" # the old proxy is gone
+username = "michael"
+
+[profile.dev]
+proxy = "dev.example.com"
+"#
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use crate::teleport::{backend, cli};
+use crate::utils::shell;
+
+/// The flags of tsh, which it also reads from the environment
+const TSH_ENV_FLAGS: [(&str, &str); 4] = [
+    ("--proxy=", "TELEPORT_PROXY"),
+    ("--cluster=", "TELEPORT_CLUSTER"),
+    ("--user=", "TELEPORT_USER"),
+    ("--auth=", "TELEPORT_AUTH"),
+];
+
+/// How `--tsh` prints the commands beam would run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// A shell-quoted command line per command
+    #[default]
+    Shell,
+    /// A JSON array of the arguments per command
+    Json,
+    /// An equivalent ssh command of OpenSSH, only for ssh sessions
+    Openssh,
+    /// Exports of the proxy, cluster and user for tsh, followed by the commands
+    Env,
+}
+
+/// A command beam would run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub argv: Vec<String>,
+    /// Whether the command runs tsh, whose flags can be given through the environment
+    pub is_tsh: bool,
+}
+
+impl Command {
+    /// Runs the tsh for the proxy with the subcommand and arguments, e.g. `["db", "login", ...]`
+    pub fn tsh(proxy: Option<&str>, args: &[String]) -> Command {
+        Command {
+            argv: backend::tsh(proxy).command_line(&args[0], &args[1..]),
+            is_tsh: true,
+        }
+    }
+
+    pub fn other(argv: Vec<String>) -> Command {
+        Command {
+            argv,
+            is_tsh: false,
+        }
+    }
+}
+
+/// Prints the commands instead of running them
+pub fn print(format: Format, commands: &[Command]) -> Result<()> {
+    println!("{}", render(format, commands)?);
+    Ok(())
+}
+
+pub fn render(format: Format, commands: &[Command]) -> Result<String> {
+    let lines = match format {
+        Format::Shell => commands
+            .iter()
+            .map(|command| shell::join(&command.argv))
+            .collect(),
+        Format::Json => commands
+            .iter()
+            .map(|command| serde_json::to_string(&command.argv))
+            .collect::<Result<_, _>>()?,
+        Format::Openssh => {
+            return Err(anyhow!(
+                "The openssh format is only available for ssh sessions, e.g. beam connect"
+            ))
+        }
+        Format::Env => render_env(commands, std::env::var(cli::TELEPORT_HOME_ENV).ok()),
+    };
+    Ok(lines.join("\n"))
+}
+
+/// Moves the flags of tsh into exports. A flag differing from an earlier export stays on its command
+fn render_env(commands: &[Command], tsh_home: Option<String>) -> Vec<String> {
+    let mut env: Vec<(&str, String)> = vec![];
+    if let Some(tsh_home) = tsh_home {
+        env.push(("TELEPORT_HOME", tsh_home));
+    }
+
+    let mut command_lines = vec![];
+    for command in commands {
+        let mut argv = vec![];
+        for arg in &command.argv {
+            let exported = TSH_ENV_FLAGS.iter().find_map(|(flag, name)| {
+                let value = arg.strip_prefix(flag).filter(|_| command.is_tsh)?;
+                match env.iter().find(|(key, _)| key == name) {
+                    Some((_, exported)) => Some(exported == value),
+                    None => {
+                        env.push((name, value.to_string()));
+                        Some(true)
+                    }
+                }
+            });
+            if exported != Some(true) {
+                argv.push(arg.as_str());
+            }
+        }
+        command_lines.push(shell::join(&argv));
+    }
+
+    env.iter()
+        .map(|(name, value)| format!("export {}={}", name, shell::quote(value)))
+        .chain(command_lines)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands() -> Vec<Command> {
+        let argv = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        vec![
+            Command {
+                argv: argv(&[
+                    "tsh",
+                    "kube",
+                    "login",
+                    "--proxy=teleport.example.com",
+                    "prod",
+                ]),
+                is_tsh: true,
+            },
+            Command::other(argv(&[
+                "kubectl",
+                "config",
+                "set-context",
+                "--current",
+                "--cluster=prod",
+                "--namespace=team a",
+            ])),
+        ]
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(Format::Shell, &commands()).unwrap(),
+            "tsh kube login --proxy=teleport.example.com prod\n\
+             kubectl config set-context --current --cluster=prod '--namespace=team a'"
+        );
+        assert_eq!(
+            render(Format::Json, &commands()[..1]).unwrap(),
+            r#"["tsh","kube","login","--proxy=teleport.example.com","prod"]"#
+        );
+        assert!(render(Format::Openssh, &commands()).is_err());
+    }
+
+    #[test]
+    fn test_render_env() {
+        assert_eq!(
+            render_env(&commands(), Some("/home/dzefo/.tsh".to_owned())),
+            vec![
+                "export TELEPORT_HOME=/home/dzefo/.tsh",
+                "export TELEPORT_PROXY=teleport.example.com",
+                "tsh kube login prod",
+                "kubectl config set-context --current --cluster=prod '--namespace=team a'"
+            ]
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// Errors for configuration, which is missing or invalid and can't be fixed without user interaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    NoProfiles,
+    NoDefaultProfile,
+    MultipleDefaultProfiles,
+    InteractionRequired(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoProfiles => write!(
+                f,
+                "No profiles found. Please create one using beam configure, or set --proxy (BEAM_PROXY) to run without a profile"
+            ),
+            ConfigError::NoDefaultProfile => write!(
+                f,
+                "No default profile found. Please create a default profile."
+            ),
+            ConfigError::MultipleDefaultProfiles => write!(
+                f,
+                "Multiple default profiles found. Please fix your profiles.toml"
+            ),
+            ConfigError::InteractionRequired(action) => write!(
+                f,
+                "{} requires an interactive terminal, but beam is running in non-interactive mode",
+                action
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
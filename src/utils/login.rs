@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap as Map;
+
+use crate::teleport::node::Node;
+
+/// Maps the nodes matching a hostname pattern and/or labels to a login
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
+pub struct LoginRule {
+    pub host_pattern: Option<String>,
+    pub label_selector: Option<Map<String, String>>,
+    /// The login to use, which may be a template like the username
+    pub login: String,
+}
+
+impl LoginRule {
+    /// Whether the rule applies to the node. A rule without a host_pattern and label_selector applies to every node
+    pub fn matches(&self, node: &Node) -> Result<bool> {
+        if let Some(host_pattern) = &self.host_pattern {
+            if !Regex::new(host_pattern)?.is_match(&node.spec.hostname) {
+                return Ok(false);
+            }
+        }
+        if let Some(label_selector) = &self.label_selector {
+            if !node.has_labels(label_selector) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut conditions = vec![];
+        if let Some(host_pattern) = &self.host_pattern {
+            conditions.push(format!("host_pattern {}", host_pattern));
+        }
+        if let Some(label_selector) = &self.label_selector {
+            conditions.push(format!(
+                "labels {}",
+                label_selector
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ));
+        }
+        if conditions.is_empty() {
+            conditions.push("any host".to_string());
+        }
+        conditions.join(" and ")
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if let Some(host_pattern) = &self.host_pattern {
+            if let Err(err) = Regex::new(host_pattern) {
+                problems.push(format!(
+                    "host_pattern {} is not a valid regex: {}",
+                    host_pattern, err
+                ));
+            }
+        }
+        if self.login.is_empty() {
+            problems.push("login must not be empty".to_string());
+        }
+        problems.extend(validate_template(&self.login));
+        problems
+    }
+}
+
+fn placeholder() -> Regex {
+    Regex::new(r"\{([^{}]*)\}").unwrap()
+}
+
+/// Fills in the placeholders of a login template:
+/// `{user}` is the local username, `{hostname}` the hostname of the node and `{label.<key>}` the value of one of its labels
+pub fn render(template: &str, node: &Node) -> Result<String> {
+    let mut error = None;
+    let login = placeholder().replace_all(template, |captures: &Captures| {
+        let name = &captures[1];
+        let value = match name {
+            "user" => Some(whoami::username()),
+            "hostname" => Some(node.spec.hostname.clone()),
+            _ => name
+                .strip_prefix("label.")
+                .and_then(|key| node.label(key).cloned()),
+        };
+        value.unwrap_or_else(|| {
+            error.get_or_insert_with(|| {
+                anyhow!(
+                    "Could not fill in {{{}}} of login {} for host {}",
+                    name,
+                    template,
+                    node.spec.hostname
+                )
+            });
+            String::new()
+        })
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(login.into_owned()),
+    }
+}
+
+/// Fills in a username template for logging in to teleport, which doesn't concern a node.
+/// A template depending on the node, e.g. through `{hostname}`, gives no teleport user
+pub fn render_user(template: &str) -> Option<String> {
+    let mut depends_on_node = false;
+    let user = placeholder().replace_all(template, |captures: &Captures| match &captures[1] {
+        "user" => whoami::username(),
+        _ => {
+            depends_on_node = true;
+            String::new()
+        }
+    });
+    match depends_on_node {
+        true => None,
+        false => Some(user.into_owned()),
+    }
+}
+
+pub fn validate_template(template: &str) -> Vec<String> {
+    placeholder()
+        .captures_iter(template)
+        .map(|captures| captures[1].to_string())
+        .filter(|name| name != "user" && name != "hostname" && !name.starts_with("label."))
+        .map(|name| format!("login {} uses unknown placeholder {{{}}}", template, name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let node = Node::new(
+            "web-1",
+            HashMap::from([("os".to_owned(), "ubuntu".to_owned())]),
+        );
+
+        assert_eq!(render("ubuntu", &node).unwrap(), "ubuntu");
+        assert_eq!(
+            render("{label.os}-{hostname}", &node).unwrap(),
+            "ubuntu-web-1"
+        );
+        assert_eq!(render("{user}", &node).unwrap(), whoami::username());
+        assert!(render("{label.team}", &node).is_err());
+        assert_eq!(render_user("{user}"), Some(whoami::username()));
+        assert_eq!(render_user("ubuntu"), Some("ubuntu".to_owned()));
+        assert_eq!(render_user("{label.os}"), None);
+        assert_eq!(validate_template("{label.os}-{hostname}").len(), 0);
+        assert_eq!(validate_template("{host}").len(), 1);
+    }
+
+    #[test]
+    fn test_login_rule_matches() {
+        let node = Node::new(
+            "db-1",
+            HashMap::from([("os".to_owned(), "amazon".to_owned())]),
+        );
+        let rule = LoginRule {
+            host_pattern: Some("^db-".to_owned()),
+            label_selector: Some(Map::from([("os".to_owned(), "amazon".to_owned())])),
+            login: "ec2-user".to_owned(),
+        };
+        assert!(rule.matches(&node).unwrap());
+
+        let rule = LoginRule {
+            label_selector: Some(Map::from([("os".to_owned(), "ubuntu".to_owned())])),
+            ..rule
+        };
+        assert!(!rule.matches(&node).unwrap());
+        assert!(LoginRule::default().matches(&node).unwrap());
+    }
+}
@@ -1,6 +1,15 @@
 pub mod config;
+pub mod document;
+pub mod dry_run;
+pub mod error;
+pub mod login;
+pub mod paths;
 pub mod profile;
 pub mod profiles;
+pub mod project;
+pub mod schema;
+pub mod settings;
+pub mod shell;
 pub mod skim;
 pub mod spinner;
 pub mod version;
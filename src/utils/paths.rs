@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use std::{ffi::OsString, fs, path::PathBuf, sync::RwLock};
+
+const CONFIG_DIR_ENV: &str = "BEAM_CONFIG_DIR";
+const LEGACY_DIR: &str = ".beam";
+
+lazy_static! {
+    static ref CONFIG_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Relocates the config directory, e.g. through the `--config-dir` flag. Takes precedence over `BEAM_CONFIG_DIR`
+pub fn set_config_dir(dir: PathBuf) {
+    *CONFIG_DIR_OVERRIDE.write().unwrap() = Some(dir);
+}
+
+/// The explicitly relocated config directory, if there is one
+fn config_dir_override() -> Option<PathBuf> {
+    CONFIG_DIR_OVERRIDE
+        .read()
+        .unwrap()
+        .clone()
+        .or_else(|| non_empty_env(CONFIG_DIR_ENV).map(PathBuf::from))
+}
+
+/// The directory holding the profiles. Defaults to `$XDG_CONFIG_HOME/beam` or `~/.config/beam`
+pub fn config_dir() -> PathBuf {
+    match config_dir_override() {
+        Some(dir) => dir,
+        None => xdg_dir(non_empty_env("XDG_CONFIG_HOME"), home_dir(), ".config"),
+    }
+}
+
+/// The directory holding the node cache. Defaults to `$XDG_CACHE_HOME/beam` or `~/.cache/beam`.
+/// A relocated config directory also holds the cache
+pub fn cache_dir() -> PathBuf {
+    match config_dir_override() {
+        Some(dir) => dir.join("cache"),
+        None => xdg_dir(non_empty_env("XDG_CACHE_HOME"), home_dir(), ".cache"),
+    }
+}
+
+pub fn profiles_file() -> PathBuf {
+    config_dir().join("profiles.toml")
+}
+
+/// Moves the profiles from `~/.beam`, which was used before the XDG directories, to the config directory.
+/// The old cache is dropped, as it will be rebuilt on demand
+pub fn migrate_legacy_dir() -> Result<()> {
+    if config_dir_override().is_some() {
+        return Ok(());
+    }
+    let legacy_dir = home_dir().join(LEGACY_DIR);
+    if !legacy_dir.is_dir() {
+        return Ok(());
+    }
+
+    let legacy_profiles = legacy_dir.join("profiles.toml");
+    let profiles = profiles_file();
+    if legacy_profiles.exists() && !profiles.exists() {
+        fs::create_dir_all(config_dir())?;
+        fs::copy(&legacy_profiles, &profiles).with_context(|| {
+            format!(
+                "Could not migrate {} to {}",
+                legacy_profiles.display(),
+                profiles.display()
+            )
+        })?;
+        fs::remove_file(&legacy_profiles)?;
+        eprintln!(
+            "Moved your profiles from {} to {}",
+            legacy_profiles.display(),
+            profiles.display()
+        );
+    }
+
+    let legacy_cache = legacy_dir.join("cache");
+    if legacy_cache.is_dir() {
+        fs::remove_dir_all(legacy_cache)?;
+    }
+    // Only removes the old directory, if nothing else is left in it
+    let _ = fs::remove_dir(legacy_dir);
+
+    Ok(())
+}
+
+fn xdg_dir(xdg_home: Option<OsString>, home_dir: PathBuf, fallback: &str) -> PathBuf {
+    match xdg_home {
+        Some(xdg_home) => PathBuf::from(xdg_home).join("beam"),
+        None => home_dir.join(fallback).join("beam"),
+    }
+}
+
+fn home_dir() -> PathBuf {
+    home::home_dir().expect("Could not find home directory")
+}
+
+fn non_empty_env(name: &str) -> Option<OsString> {
+    std::env::var_os(name).filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xdg_dir() {
+        let home_dir = PathBuf::from("/home/dzefo");
+        assert_eq!(
+            xdg_dir(Some("/tmp/config".into()), home_dir.clone(), ".config"),
+            PathBuf::from("/tmp/config/beam")
+        );
+        assert_eq!(
+            xdg_dir(None, home_dir, ".cache"),
+            PathBuf::from("/home/dzefo/.cache/beam")
+        );
+    }
+}
@@ -1,10 +1,12 @@
 use anyhow::{anyhow, Result};
 use colored::Colorize;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::process;
+use std::{collections::BTreeMap as Map, fmt, path::PathBuf, process};
 
-use crate::utils::{config::Config, profiles::Profiles};
+use crate::teleport::node::Node;
+use crate::utils::{config::Config, login, profiles::Profiles, schema};
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
 pub struct Profile {
@@ -12,9 +14,30 @@ pub struct Profile {
     pub name: String,
     pub default: bool,
     pub host_pattern: Option<String>,
+    /// Labels a node must have for this profile to be auto-selected
+    pub label_selector: Option<Map<String, String>>,
     pub priority: Option<i32>,
     #[serde(flatten)]
     pub config: Config,
+    #[serde(skip)]
+    pub source: ProfileSource,
+}
+
+/// Where a profile was loaded from. Shared profiles are read-only for beam.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ProfileSource {
+    #[default]
+    Personal,
+    Shared(PathBuf),
+}
+
+impl fmt::Display for ProfileSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileSource::Personal => write!(f, "personal"),
+            ProfileSource::Shared(path) => write!(f, "shared ({})", path.display()),
+        }
+    }
 }
 
 impl Profile {
@@ -28,8 +51,10 @@ impl Profile {
             name,
             default,
             host_pattern,
+            label_selector: None,
             priority: None,
             config: config.unwrap_or_default(),
+            source: ProfileSource::Personal,
         }
     }
 
@@ -243,13 +268,126 @@ impl Profile {
     }
 
     pub fn get(name: &str) -> Result<Profile> {
-        let profiles = Profiles::get_profiles().unwrap();
-        let profile = profiles.profiles.get(name);
+        let profiles = Profiles::get()?;
+        let profile = profiles.into_iter().find(|p| p.name == name);
         match profile {
-            Some(profile) => Ok(profile.to_owned()),
+            Some(profile) => Ok(profile),
             None => Err(anyhow!("Could not find profile {}", name)),
         }
     }
+
+    /// Whether this profile should be auto-selected for the node. A profile without any
+    /// match rule never matches, otherwise all of its rules have to match
+    pub fn matches(&self, node: &Node) -> Result<bool> {
+        Ok(self.explain_match(node)?.0)
+    }
+
+    /// Whether this profile matches the node, together with a human readable reason
+    pub fn explain_match(&self, node: &Node) -> Result<(bool, String)> {
+        if self.host_pattern.is_none() && self.label_selector.is_none() {
+            return Ok((false, "has no host_pattern or label_selector".to_string()));
+        }
+        let mut reasons = vec![];
+        if let Some(host_pattern) = &self.host_pattern {
+            if !Regex::new(host_pattern)?.is_match(&node.spec.hostname) {
+                return Ok((
+                    false,
+                    format!("host_pattern {} does not match", host_pattern),
+                ));
+            }
+            reasons.push(format!("host_pattern {} matches", host_pattern));
+        }
+        if let Some(label_selector) = &self.label_selector {
+            if let Some((key, value)) = label_selector
+                .iter()
+                .find(|(key, value)| node.label(key) != Some(value))
+            {
+                return Ok((false, format!("label {}={} is missing", key, value)));
+            }
+            reasons.push(format!(
+                "labels {} match",
+                label_selector
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ));
+        }
+        Ok((true, reasons.join(" and ")))
+    }
+
+    /// Checks the profile for problems, which would otherwise only show up when connecting
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if let Some(host_pattern) = &self.host_pattern {
+            if let Err(err) = Regex::new(host_pattern) {
+                problems.push(format!(
+                    "host_pattern {} is not a valid regex: {}",
+                    host_pattern, err
+                ));
+            }
+        }
+
+        let config = &self.config;
+        if let Some(username) = &config.username {
+            problems.extend(login::validate_template(username));
+        }
+        for (index, rule) in config.login_rules.iter().flatten().enumerate() {
+            problems.extend(
+                rule.validate()
+                    .into_iter()
+                    .map(|problem| format!("login_rules[{}]: {}", index, problem)),
+            );
+        }
+        if config.listen_port == Some(0) {
+            problems.push("listen_port must be between 1 and 65535".to_string());
+        }
+        if config.remote_port == Some(0) {
+            problems.push("remote_port must be between 1 and 65535".to_string());
+        }
+        if config.enable_port_forwarding == Some(true) {
+            if config.listen_port.is_none() {
+                problems.push("port forwarding is enabled, but listen_port is not set".to_string());
+            }
+            if config
+                .remote_host
+                .as_ref()
+                .is_none_or(|host| host.is_empty())
+            {
+                problems.push("port forwarding is enabled, but remote_host is not set".to_string());
+            }
+            if config.remote_port.is_none() {
+                problems.push("port forwarding is enabled, but remote_port is not set".to_string());
+            }
+        }
+
+        problems
+    }
+
+    /// The number of match rules, used to prefer the most specific of several matching profiles
+    pub fn specificity(&self) -> usize {
+        usize::from(self.host_pattern.is_some())
+            + self
+                .label_selector
+                .as_ref()
+                .map_or(0, |labels| labels.len())
+    }
+
+    pub fn is_shared(&self) -> bool {
+        matches!(self.source, ProfileSource::Shared(_))
+    }
+
+    pub fn ensure_writable(&self) -> Result<()> {
+        match &self.source {
+            ProfileSource::Personal => Ok(()),
+            ProfileSource::Shared(path) => Err(anyhow!(
+                "Profile {} is read-only, as it is managed in the shared profiles file {}.\nTo change it, add a profile with the same name to your personal profiles.toml",
+                self.name.red(),
+                path.display()
+            )),
+        }
+    }
 }
 
 impl From<Vec<Profile>> for Profiles {
@@ -259,6 +397,8 @@ impl From<Vec<Profile>> for Profiles {
             .map(|profile| (profile.name.to_owned(), profile))
             .collect();
         Profiles {
+            schema_version: schema::SCHEMA_VERSION,
+            sources: None,
             profiles: profile_map,
         }
     }
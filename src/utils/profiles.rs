@@ -1,44 +1,48 @@
 use anyhow::{anyhow, Context, Result};
-use lazy_static::lazy_static;
-use regex::Regex;
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap as Map, fs};
+use std::{
+    collections::BTreeMap as Map,
+    fs, io,
+    path::{Path, PathBuf},
+};
 
-use crate::{command, utils::profile::Profile};
+use crate::{
+    command,
+    teleport::node::Node,
+    utils::{
+        document,
+        error::ConfigError,
+        paths,
+        profile::{Profile, ProfileSource},
+        schema,
+    },
+};
 
-const BEAM_PROFILES_PATH: &str = ".beam/profiles.toml";
-
-lazy_static! {
-    #[derive(Debug, PartialEq, Eq, Default)]
-    pub static ref DEFAULT_PROFILE: Profile = match Profiles::get_default() {
-        Ok(profile) => profile,
-        Err(err) => {
-            println!("{}", err);
-            command::configure::default::Default::run().unwrap();
-            Profiles::get_default().unwrap()
-        }
-    };
-}
+const SHARED_PROFILES_ENV: &str = "BEAM_SHARED_PROFILES";
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Profiles {
-    #[serde(rename = "profile")]
+    #[serde(default)]
+    pub schema_version: i64,
+    /// Paths of read-only profile files, which are layered under the personal profiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<String>>,
+    #[serde(rename = "profile", default)]
     pub profiles: Map<String, Profile>,
 }
 
 impl From<Profiles> for Vec<Profile> {
     fn from(profiles: Profiles) -> Self {
-        profiles
-            .profiles
-            .into_iter()
-            .map(|(_, v)| v)
-            .collect::<Vec<Profile>>()
+        profiles.profiles.into_values().collect::<Vec<Profile>>()
     }
 }
 
 impl Profiles {
     pub fn write(profile: Profile) -> Result<()> {
-        let mut profiles = Profiles::get_profiles().unwrap_or_default();
+        profile.ensure_writable()?;
+        Profiles::ensure_valid(std::slice::from_ref(&profile))?;
+        let mut profiles = Profiles::get_personal()?;
 
         if profile.default {
             profiles
@@ -47,39 +51,84 @@ impl Profiles {
                 .for_each(|p| p.default = false);
         }
 
-        let key = profile.name.as_str();
-        profiles
-            .profiles
-            .entry(key.to_string())
-            .or_insert_with(Profile::default);
-        profiles.profiles.insert(key.to_string(), profile);
+        profiles.profiles.insert(profile.name.to_owned(), profile);
         profiles.save()?;
         Ok(())
     }
 
     pub fn save(&self) -> Result<()> {
-        let profiles_path = home::home_dir().unwrap().join(BEAM_PROFILES_PATH);
+        let profiles_path = paths::profiles_file();
         if !profiles_path.exists() {
             fs::create_dir_all(profiles_path.parent().unwrap())?;
             fs::OpenOptions::new()
                 .create(true)
+                .truncate(true)
                 .write(true)
                 .open(&profiles_path)?;
         }
-        let profiles_str = if self.profiles.is_empty() {
+        for key in Profiles::unknown_keys()? {
+            eprintln!(
+                "{} unknown key {} in {} is not supported and will be dropped",
+                "Warning:".yellow(),
+                key.yellow(),
+                profiles_path.display()
+            );
+        }
+
+        // Shared profiles are never persisted into the personal profiles file
+        let personal = Profiles {
+            schema_version: schema::SCHEMA_VERSION,
+            sources: self.sources.clone(),
+            profiles: self
+                .profiles
+                .iter()
+                .filter(|(_, profile)| !profile.is_shared())
+                .map(|(name, profile)| (name.to_owned(), profile.to_owned()))
+                .collect(),
+        };
+        let profiles_str = if personal.profiles.is_empty() && personal.sources.is_none() {
             "".to_string()
         } else {
-            toml::to_string(&self)?
+            personal.to_toml()?
         };
-        std::fs::write(profiles_path, profiles_str)?;
+        // Only the changed keys are rewritten, so comments in the file survive
+        let original_str = fs::read_to_string(&profiles_path)?;
+        std::fs::write(
+            profiles_path,
+            document::update(&original_str, &profiles_str)?,
+        )?;
         Ok(())
     }
 
+    pub fn to_toml(&self) -> Result<String> {
+        // Going through a Value puts plain keys before tables, which flattened profiles don't guarantee
+        Ok(toml::to_string(&toml::Value::try_from(self)?)?)
+    }
+
     pub fn get_profiles() -> Result<Profiles> {
-        let profiles_path = home::home_dir().unwrap().join(BEAM_PROFILES_PATH);
-        let profiles_str = std::fs::read_to_string(profiles_path)
+        let profiles_path = paths::profiles_file();
+        let profiles_str = std::fs::read_to_string(&profiles_path)
             .context("Error while reading Profiles from profiles.toml")?;
-        let mut profiles_map: Profiles = toml::from_str(&profiles_str)?;
+        let mut profiles_value: toml::Value = toml::from_str(&profiles_str)?;
+
+        if let Some(old_version) = schema::migrate(&mut profiles_value)? {
+            // Keeping the old file, before rewriting it in the new layout
+            let backup_path = profiles_path.with_extension(format!("toml.v{}.bak", old_version));
+            fs::copy(&profiles_path, &backup_path)?;
+            fs::write(
+                &profiles_path,
+                document::update(&profiles_str, &toml::to_string(&profiles_value)?)?,
+            )?;
+            // On stderr, as the migration may run while stdout is parsed, e.g. with list --format json
+            eprintln!(
+                "Migrated {} to schema version {}. The old file was saved as {}",
+                profiles_path.display(),
+                schema::SCHEMA_VERSION,
+                backup_path.display()
+            );
+        }
+
+        let mut profiles_map: Profiles = profiles_value.try_into()?;
         for (key, profile) in profiles_map.profiles.iter_mut() {
             profile.name = key.to_string();
         }
@@ -87,16 +136,146 @@ impl Profiles {
         Ok(profiles_map)
     }
 
-    pub fn get() -> Result<Vec<Profile>> {
-        let profiles_map = Profiles::get_profiles();
-        match profiles_map {
-            Ok(profiles_map) => {
-                let profiles: Vec<Profile> = profiles_map.into();
-                Profiles::verify_profiles_integrity(&profiles)?;
-                Ok(profiles)
+    /// The personal profiles. A missing profiles.toml holds none, while any other error,
+    /// e.g. a newer schema version, is returned
+    pub fn get_personal() -> Result<Profiles> {
+        match Profiles::get_profiles() {
+            Err(err)
+                if err
+                    .downcast_ref::<io::Error>()
+                    .is_some_and(|err| err.kind() == io::ErrorKind::NotFound) =>
+            {
+                Ok(Profiles::default())
             }
-            Err(_) => Ok(vec![]),
+            result => result,
+        }
+    }
+
+    pub fn get_shared(personal: &Profiles) -> Result<Vec<Profiles>> {
+        Profiles::shared_paths(personal)
+            .into_iter()
+            .map(|path| {
+                let profiles_str = std::fs::read_to_string(&path).with_context(|| {
+                    format!(
+                        "Error while reading shared profiles from {}",
+                        path.display()
+                    )
+                })?;
+                // Shared files are read-only, so they are only migrated in memory
+                let mut profiles_value: toml::Value =
+                    toml::from_str(&profiles_str).with_context(|| {
+                        format!("Error while parsing shared profiles in {}", path.display())
+                    })?;
+                schema::migrate(&mut profiles_value)?;
+                let mut profiles_map: Profiles = profiles_value.try_into().with_context(|| {
+                    format!("Error while parsing shared profiles in {}", path.display())
+                })?;
+                for (key, profile) in profiles_map.profiles.iter_mut() {
+                    profile.name = key.to_string();
+                    profile.source = ProfileSource::Shared(path.clone());
+                }
+                Ok(profiles_map)
+            })
+            .collect()
+    }
+
+    /// Shared profile files from the `sources` list of the personal profiles,
+    /// followed by the ones set through the `BEAM_SHARED_PROFILES` environment variable
+    pub fn shared_paths(personal: &Profiles) -> Vec<PathBuf> {
+        let home_dir = home::home_dir().unwrap();
+        let profiles_dir = paths::config_dir();
+
+        let mut paths: Vec<PathBuf> = personal
+            .sources
+            .iter()
+            .flatten()
+            .map(|source| match source.strip_prefix("~/") {
+                Some(source) => home_dir.join(source),
+                None => profiles_dir.join(source),
+            })
+            .collect();
+        if let Some(env_paths) = std::env::var_os(SHARED_PROFILES_ENV) {
+            paths.extend(std::env::split_paths(&env_paths).filter(|p| p != Path::new("")));
+        }
+        paths
+    }
+
+    /// Layers the personal profiles over the shared ones. Later shared files override
+    /// earlier ones and personal profiles override shared profiles with the same name.
+    pub fn merge(personal: Profiles, shared: Vec<Profiles>) -> Vec<Profile> {
+        let mut merged: Map<String, Profile> = Map::new();
+        for shared_profiles in shared {
+            merged.extend(shared_profiles.profiles);
+        }
+
+        // A personal default profile always wins over a shared one
+        if personal.profiles.values().any(|profile| profile.default) {
+            merged
+                .values_mut()
+                .for_each(|profile| profile.default = false);
+        }
+        merged.extend(personal.profiles);
+
+        merged.into_values().collect()
+    }
+
+    /// Lists the keys in the personal profiles.toml, which beam doesn't know about
+    pub fn unknown_keys() -> Result<Vec<String>> {
+        let profiles_path = paths::profiles_file();
+        if !profiles_path.exists() {
+            return Ok(vec![]);
+        }
+        let profiles_str = fs::read_to_string(&profiles_path)?;
+        let original: toml::Value = toml::from_str(&profiles_str)?;
+        let profiles: Profiles = original.clone().try_into()?;
+        let known = toml::Value::try_from(&profiles)?;
+        Ok(schema::unknown_keys(&original, &known))
+    }
+
+    /// Loads the personal and shared profiles, without validating them
+    pub fn load() -> Result<Vec<Profile>> {
+        let personal = Profiles::get_personal()?;
+        let shared = Profiles::get_shared(&personal)?;
+        Ok(Profiles::merge(personal, shared))
+    }
+
+    pub fn get() -> Result<Vec<Profile>> {
+        let profiles = Profiles::load()?;
+        if profiles.is_empty() {
+            return Ok(profiles);
         }
+        Profiles::ensure_valid(&profiles)?;
+        Profiles::verify_profiles_integrity(&profiles)?;
+        Ok(profiles)
+    }
+
+    /// Collects the problems of all profiles
+    pub fn validate(profiles: &[Profile]) -> Vec<String> {
+        profiles
+            .iter()
+            .flat_map(|profile| {
+                profile
+                    .validate()
+                    .into_iter()
+                    .map(|problem| format!("Profile {}: {}", profile.name, problem))
+            })
+            .collect()
+    }
+
+    pub fn ensure_valid(profiles: &[Profile]) -> Result<()> {
+        let problems = Profiles::validate(profiles);
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Found {} problem(s) in your profiles:\n{}\nRun beam doctor to check your whole setup",
+            problems.len(),
+            problems
+                .iter()
+                .map(|problem| format!("  - {}", problem))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))
     }
 
     pub fn get_default() -> Result<Profile> {
@@ -104,27 +283,53 @@ impl Profiles {
         Profiles::verify_profiles_integrity(&profiles)
     }
 
+    /// Gets the default profile. When it is missing, the configure wizard is started,
+    /// unless beam is running non-interactively
+    pub fn resolve_default(interactive: bool) -> Result<Profile> {
+        match Profiles::get_default() {
+            Ok(profile) => Ok(profile),
+            Err(err) if interactive && err.is::<ConfigError>() => {
+                println!("{}", err);
+                command::configure::default::Default::run()?;
+                Profiles::get_default()
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     pub fn verify_profiles_integrity(profiles: &[Profile]) -> Result<Profile> {
         if profiles.is_empty() {
-            return Err(anyhow!("No profiles found"));
+            return Err(ConfigError::NoProfiles.into());
         }
 
         let default_profiles_count = profiles.iter().filter(|profile| profile.default).count();
         match default_profiles_count {
-            0 => Err(anyhow!(
-                "No default profile found. Please create a default profile."
-            )),
+            0 => Err(ConfigError::NoDefaultProfile.into()),
             1 => Ok(profiles
                 .iter()
                 .find(|profile| profile.default)
                 .unwrap()
                 .clone()),
-            _ => Err(anyhow!(
-                "Multiple default profiles found. Please fix your profiles.toml"
-            )),
+            _ => Err(ConfigError::MultipleDefaultProfiles.into()),
         }
     }
 
+    /// One profile for every distinct proxy and cluster, preferring the default profile and otherwise the first by name.
+    /// Profiles without a proxy are left out
+    pub fn by_proxy(mut profiles: Vec<Profile>) -> Vec<Profile> {
+        profiles.sort_by(|a, b| b.default.cmp(&a.default).then_with(|| a.name.cmp(&b.name)));
+        let mut seen = vec![];
+        profiles.retain(|profile| {
+            let key = (profile.config.proxy.clone(), profile.config.cluster.clone());
+            if key.0.is_none() || seen.contains(&key) {
+                return false;
+            }
+            seen.push(key);
+            true
+        });
+        profiles
+    }
+
     pub fn get_names(profiles: &[Profile]) -> Result<Vec<String>> {
         Ok(profiles
             .iter()
@@ -138,37 +343,94 @@ impl Profiles {
             .collect::<Vec<_>>())
     }
 
-    pub fn get_matching(hostname: &str, profiles: Vec<Profile>) -> Result<Option<Profile>> {
-        let mut matched_profiles = vec![];
-
-        for profile in profiles {
-            if profile.host_pattern.is_some() {
-                let regex = Regex::new(profile.host_pattern.as_ref().unwrap())?;
-                if regex.is_match(hostname) {
-                    matched_profiles.push(profile);
-                }
-            }
-        }
+    /// Gets the profile to auto-select for the node. Matching profiles are ranked by their
+    /// priority (lowest first, profiles without one last), then by how specific their match
+    /// rules are and finally by their name
+    pub fn get_matching(node: &Node, profiles: Vec<Profile>) -> Result<Option<Profile>> {
+        let candidates = Profiles::explain_matching(node, profiles)?;
+        Ok(candidates
+            .into_iter()
+            .find(|candidate| candidate.matched)
+            .map(|candidate| candidate.profile))
+    }
 
-        match matched_profiles.len() {
-            0 => Ok(None),
-            1 => Ok(Some(matched_profiles[0].clone())),
-            _ => {
-                matched_profiles.retain(|profile| profile.priority.is_some());
-                matched_profiles.sort_by(|a, b| a.priority.cmp(&b.priority));
+    /// Checks every profile against the node. Matching candidates come first, in the order
+    /// `get_matching` ranks them
+    pub fn explain_matching(node: &Node, profiles: Vec<Profile>) -> Result<Vec<MatchCandidate>> {
+        let mut candidates = profiles
+            .into_iter()
+            .map(|profile| {
+                let (matched, reason) = profile.explain_match(node)?;
+                Ok(MatchCandidate {
+                    profile,
+                    matched,
+                    reason,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-                Ok(if matched_profiles.is_empty() {
-                    None
-                } else {
-                    Some(matched_profiles[0].clone())
+        candidates.sort_by(|a, b| {
+            b.matched
+                .cmp(&a.matched)
+                .then_with(|| {
+                    a.profile
+                        .priority
+                        .is_none()
+                        .cmp(&b.profile.priority.is_none())
                 })
+                .then_with(|| a.profile.priority.cmp(&b.profile.priority))
+                .then_with(|| b.profile.specificity().cmp(&a.profile.specificity()))
+                .then_with(|| a.profile.name.cmp(&b.profile.name))
+        });
+        Ok(candidates)
+    }
+
+    /// Describes why the first candidate of `explain_matching` wins over the second one
+    pub fn explain_winner(candidates: &[MatchCandidate]) -> Option<String> {
+        let winner = candidates.first().filter(|c| c.matched)?;
+        let runner_up = match candidates.get(1).filter(|c| c.matched) {
+            Some(runner_up) => &runner_up.profile,
+            None => return Some("it is the only matching profile".to_string()),
+        };
+        let winner = &winner.profile;
+
+        Some(if winner.priority != runner_up.priority {
+            match runner_up.priority {
+                Some(priority) => format!(
+                    "its priority {} is lower than the priority {} of {}",
+                    winner.priority.unwrap(),
+                    priority,
+                    runner_up.name
+                ),
+                None => format!("it has a priority, while {} has none", runner_up.name),
             }
-        }
+        } else if winner.specificity() != runner_up.specificity() {
+            format!(
+                "it is more specific than {} ({} match rules instead of {})",
+                runner_up.name,
+                winner.specificity(),
+                runner_up.specificity()
+            )
+        } else {
+            format!(
+                "it has the same priority and specificity as {}, but comes first by name",
+                runner_up.name
+            )
+        })
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchCandidate {
+    pub profile: Profile,
+    pub matched: bool,
+    pub reason: String,
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use crate::utils::config::Config;
 
     use super::*;
@@ -189,9 +451,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: true,
                 host_pattern: None,
+                ..Default::default()
             },
             Profile {
                 name: "test2".to_owned(),
@@ -206,9 +470,11 @@ mod tests {
                     listen_port: Some(3306),
                     remote_port: Some(3306),
                     remote_host: Some("127.0.0.1".to_string()),
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: None,
+                ..Default::default()
             },
         ];
         let invalid_profiles = [
@@ -225,9 +491,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: true,
                 host_pattern: None,
+                ..Default::default()
             },
             Profile {
                 name: "test2".to_owned(),
@@ -242,15 +510,31 @@ mod tests {
                     listen_port: Some(3306),
                     remote_port: Some(3306),
                     remote_host: Some("127.0.0.1".to_string()),
+                    ..Default::default()
                 },
                 default: true,
                 host_pattern: None,
+                ..Default::default()
             },
         ];
 
         assert!(Profiles::verify_profiles_integrity(&valid_profiles).is_ok());
         assert!(Profiles::verify_profiles_integrity(&invalid_profiles).is_err());
         assert!(Profiles::verify_profiles_integrity(&[]).is_err());
+        assert_eq!(
+            Profiles::verify_profiles_integrity(&invalid_profiles)
+                .unwrap_err()
+                .downcast::<ConfigError>()
+                .unwrap(),
+            ConfigError::MultipleDefaultProfiles
+        );
+        assert_eq!(
+            Profiles::verify_profiles_integrity(&[])
+                .unwrap_err()
+                .downcast::<ConfigError>()
+                .unwrap(),
+            ConfigError::NoProfiles
+        );
     }
 
     #[test]
@@ -269,9 +553,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: true,
                 host_pattern: None,
+                ..Default::default()
             },
             Profile {
                 name: "test2".to_owned(),
@@ -286,9 +572,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: None,
+                ..Default::default()
             },
         ];
         let expected_names = vec!["test (default)", "test2"];
@@ -298,7 +586,7 @@ mod tests {
 
     #[test]
     fn test_get_matching_with_priority() {
-        let hostname = "quality.app.example.com";
+        let node = Node::new("quality.app.example.com", HashMap::new());
         let expected_profile = Profile {
             name: "test".to_owned(),
             priority: Some(0),
@@ -312,9 +600,11 @@ mod tests {
                 listen_port: None,
                 remote_port: None,
                 remote_host: None,
+                ..Default::default()
             },
             default: true,
             host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+            ..Default::default()
         };
         let profiles = [
             expected_profile.clone(),
@@ -331,9 +621,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: Some(r#"\b(dev|prod)\b.*"#.to_string()),
+                ..Default::default()
             },
             Profile {
                 name: "test3".to_owned(),
@@ -348,9 +640,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                ..Default::default()
             },
             Profile {
                 name: "test4".to_owned(),
@@ -365,14 +659,16 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                ..Default::default()
             },
         ];
         assert_eq!(
             expected_profile,
-            Profiles::get_matching(hostname, profiles.to_vec())
+            Profiles::get_matching(&node, profiles.to_vec())
                 .unwrap()
                 .unwrap()
         );
@@ -380,7 +676,7 @@ mod tests {
 
     #[test]
     fn test_get_matching_without_priority() {
-        let hostname = "quality.app.example.com";
+        let node = Node::new("quality.app.example.com", HashMap::new());
         let expected_profile = Profile {
             name: "test".to_owned(),
             priority: None,
@@ -394,9 +690,11 @@ mod tests {
                 listen_port: None,
                 remote_port: None,
                 remote_host: None,
+                ..Default::default()
             },
             default: true,
             host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+            ..Default::default()
         };
         let profiles = [
             expected_profile.clone(),
@@ -413,14 +711,16 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: Some(r#"\b(dev|prod)\b.*"#.to_string()),
+                ..Default::default()
             },
         ];
         assert_eq!(
             expected_profile,
-            Profiles::get_matching(hostname, profiles.to_vec())
+            Profiles::get_matching(&node, profiles.to_vec())
                 .unwrap()
                 .unwrap()
         );
@@ -428,8 +728,7 @@ mod tests {
 
     #[test]
     fn test_get_matching_without_priority_multiple_matching() {
-        let hostname = "quality.app.example.com";
-        let expected_result: Option<Profile> = None;
+        let node = Node::new("quality.app.example.com", HashMap::new());
         let profiles = [
             Profile {
                 name: "test".to_owned(),
@@ -444,9 +743,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: true,
                 host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                ..Default::default()
             },
             Profile {
                 name: "test2".to_owned(),
@@ -461,20 +762,23 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                ..Default::default()
             },
         ];
+        // Equally ranked profiles are ordered by name
         assert_eq!(
-            expected_result,
-            Profiles::get_matching(hostname, profiles.to_vec()).unwrap()
+            Some(profiles[0].clone()),
+            Profiles::get_matching(&node, profiles.to_vec()).unwrap()
         );
     }
 
     #[test]
     fn test_get_matching_no_match() {
-        let hostname = "quality.app.example.com";
+        let node = Node::new("quality.app.example.com", HashMap::new());
         let expected_result: Option<Profile> = None;
         let profiles = [Profile {
             name: "test".to_owned(),
@@ -489,19 +793,23 @@ mod tests {
                 listen_port: None,
                 remote_port: None,
                 remote_host: None,
+                ..Default::default()
             },
             default: false,
             host_pattern: Some(r#"\b(dev|prod)\b.*"#.to_string()),
+            ..Default::default()
         }];
         assert_eq!(
             expected_result,
-            Profiles::get_matching(hostname, profiles.to_vec()).unwrap()
+            Profiles::get_matching(&node, profiles.to_vec()).unwrap()
         );
     }
 
     #[test]
     fn test_from_profiles() {
         let profiles = Profiles {
+            schema_version: schema::SCHEMA_VERSION,
+            sources: None,
             profiles: Map::from([
                 (
                     "test".to_string(),
@@ -518,9 +826,11 @@ mod tests {
                             listen_port: None,
                             remote_port: None,
                             remote_host: None,
+                            ..Default::default()
                         },
                         default: true,
                         host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                        ..Default::default()
                     },
                 ),
                 (
@@ -538,9 +848,11 @@ mod tests {
                             listen_port: None,
                             remote_port: None,
                             remote_host: None,
+                            ..Default::default()
                         },
                         default: false,
                         host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                        ..Default::default()
                     },
                 ),
             ]),
@@ -560,9 +872,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: true,
                 host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                ..Default::default()
             },
             Profile {
                 name: "test2".to_owned(),
@@ -577,9 +891,11 @@ mod tests {
                     listen_port: None,
                     remote_port: None,
                     remote_host: None,
+                    ..Default::default()
                 },
                 default: false,
                 host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                ..Default::default()
             },
         ];
 
@@ -587,4 +903,225 @@ mod tests {
 
         assert_eq!(expected_profile_vec, profile_vec);
     }
+
+    #[test]
+    fn test_to_toml() {
+        let profiles: Profiles = toml::from_str(
+            r#"
+schema_version = 1
+
+[profile.test]
+default = true
+label_selector = { env = "prod" }
+priority = 1
+username = "{user}"
+
+[[profile.test.login_rules]]
+label_selector = { os = "ubuntu" }
+login = "ubuntu"
+"#,
+        )
+        .unwrap();
+
+        let reparsed: Profiles = toml::from_str(&profiles.to_toml().unwrap()).unwrap();
+        assert_eq!(reparsed.profiles, profiles.profiles);
+    }
+
+    #[test]
+    fn test_by_proxy() {
+        let profile = |name: &str, default: bool, proxy: Option<&str>| Profile {
+            name: name.to_owned(),
+            default,
+            config: Config {
+                proxy: proxy.map(str::to_owned),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let profiles = Profiles::by_proxy(vec![
+            profile("a-staging", false, Some("staging.example.com")),
+            profile("b-staging", false, Some("staging.example.com")),
+            profile("none", false, None),
+            profile("prod-db", false, Some("teleport.example.com")),
+            profile("prod", true, Some("teleport.example.com")),
+        ]);
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["prod", "a-staging"]);
+    }
+
+    #[test]
+    fn test_merge_shared_profiles() {
+        let shared_path = PathBuf::from("/etc/beam/shared.toml");
+        let shared_profile = |name: &str, default: bool| Profile {
+            name: name.to_owned(),
+            default,
+            config: Config {
+                proxy: Some("shared.example.com".to_owned()),
+                ..Default::default()
+            },
+            source: ProfileSource::Shared(shared_path.clone()),
+            ..Default::default()
+        };
+        let shared = Profiles {
+            schema_version: schema::SCHEMA_VERSION,
+            sources: None,
+            profiles: Map::from([
+                ("prod".to_owned(), shared_profile("prod", true)),
+                ("staging".to_owned(), shared_profile("staging", false)),
+            ]),
+        };
+        let personal_staging = Profile {
+            name: "staging".to_owned(),
+            default: true,
+            config: Config {
+                proxy: Some("personal.example.com".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let personal = Profiles {
+            schema_version: schema::SCHEMA_VERSION,
+            sources: Some(vec!["shared.toml".to_owned()]),
+            profiles: Map::from([("staging".to_owned(), personal_staging.clone())]),
+        };
+
+        let merged = Profiles::merge(personal, vec![shared]);
+
+        assert_eq!(
+            merged,
+            vec![shared_profile("prod", false), personal_staging.clone()]
+        );
+        assert!(!merged[1].is_shared());
+        assert_eq!(
+            Profiles::verify_profiles_integrity(&merged).unwrap(),
+            personal_staging
+        );
+        assert!(merged[0].ensure_writable().is_err());
+    }
+
+    #[test]
+    fn test_get_matching_label_selector() {
+        let node = Node::new(
+            "i-0a1b2c3d",
+            HashMap::from([
+                ("env".to_owned(), "prod".to_owned()),
+                ("db".to_owned(), "postgres".to_owned()),
+            ]),
+        );
+        let label_profile = Profile {
+            name: "postgres".to_owned(),
+            label_selector: Some(Map::from([("db".to_owned(), "postgres".to_owned())])),
+            ..Default::default()
+        };
+        let combined_profile = Profile {
+            name: "staging-postgres".to_owned(),
+            host_pattern: Some("^i-".to_owned()),
+            label_selector: Some(Map::from([
+                ("db".to_owned(), "postgres".to_owned()),
+                ("env".to_owned(), "staging".to_owned()),
+            ])),
+            ..Default::default()
+        };
+        let no_rules_profile = Profile {
+            name: "default".to_owned(),
+            default: true,
+            ..Default::default()
+        };
+
+        assert!(label_profile.matches(&node).unwrap());
+        assert!(!combined_profile.matches(&node).unwrap());
+        assert!(!no_rules_profile.matches(&node).unwrap());
+        assert_eq!(
+            Profiles::get_matching(
+                &node,
+                vec![label_profile.clone(), combined_profile, no_rules_profile]
+            )
+            .unwrap(),
+            Some(label_profile)
+        );
+    }
+
+    #[test]
+    fn test_explain_matching() {
+        let node = Node::new(
+            "quality.app.example.com",
+            HashMap::from([("env".to_owned(), "quality".to_owned())]),
+        );
+        let profile = |name: &str, priority: Option<i32>, labels: &[(&str, &str)]| Profile {
+            name: name.to_owned(),
+            priority,
+            host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+            label_selector: if labels.is_empty() {
+                None
+            } else {
+                Some(
+                    labels
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                )
+            },
+            ..Default::default()
+        };
+        let profiles = vec![
+            profile("a-generic", None, &[]),
+            profile("b-specific", None, &[("env", "quality")]),
+            profile("c-other-env", None, &[("env", "prod")]),
+        ];
+
+        let candidates = Profiles::explain_matching(&node, profiles.clone()).unwrap();
+        let names: Vec<&str> = candidates.iter().map(|c| c.profile.name.as_str()).collect();
+        assert_eq!(names, vec!["b-specific", "a-generic", "c-other-env"]);
+        assert!(!candidates[2].matched);
+        assert!(Profiles::explain_winner(&candidates)
+            .unwrap()
+            .contains("more specific"));
+
+        let mut prioritized = profiles;
+        prioritized.push(profile("d-prioritized", Some(5), &[]));
+        let candidates = Profiles::explain_matching(&node, prioritized).unwrap();
+        assert_eq!(candidates[0].profile.name, "d-prioritized");
+        assert!(Profiles::explain_winner(&candidates)
+            .unwrap()
+            .contains("has a priority"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let profiles = [
+            Profile {
+                name: "valid".to_owned(),
+                default: true,
+                host_pattern: Some(r#"\b(quality|staging)\b.*"#.to_string()),
+                ..Default::default()
+            },
+            Profile {
+                name: "invalid".to_owned(),
+                host_pattern: Some("(quality".to_string()),
+                config: Config {
+                    enable_port_forwarding: Some(true),
+                    listen_port: Some(0),
+                    remote_port: Some(3306),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+
+        let problems = Profiles::validate(&profiles);
+        assert_eq!(problems.len(), 3);
+        assert!(
+            problems[0].starts_with("Profile invalid: host_pattern (quality is not a valid regex")
+        );
+        assert_eq!(
+            problems[1],
+            "Profile invalid: listen_port must be between 1 and 65535"
+        );
+        assert_eq!(
+            problems[2],
+            "Profile invalid: port forwarding is enabled, but remote_host is not set"
+        );
+        assert!(Profiles::ensure_valid(&profiles[..1]).is_ok());
+        assert!(Profiles::ensure_valid(&profiles).is_err());
+    }
 }
@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::utils::{config::Config, profile::Profile};
+
+const PROJECT_FILE_NAME: &str = ".beam.toml";
+
+/// Per-project settings from a `.beam.toml`, found in the working directory or one of its parents
+#[derive(Debug, Deserialize, Default, Clone, PartialEq, Eq)]
+pub struct Project {
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// The profile to use instead of the default profile
+    pub profile: Option<String>,
+    #[serde(flatten)]
+    pub config: Config,
+}
+
+impl Project {
+    pub fn get() -> Result<Project> {
+        let current_dir = std::env::current_dir()?;
+        match Project::find(&current_dir) {
+            Some(path) => Project::read(&path),
+            None => Ok(Project::default()),
+        }
+    }
+
+    pub fn find(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    pub fn read(path: &Path) -> Result<Project> {
+        let project_str = std::fs::read_to_string(path)
+            .with_context(|| format!("Error while reading {}", path.display()))?;
+        let mut project: Project = toml::from_str(&project_str)
+            .with_context(|| format!("Error while parsing {}", path.display()))?;
+        project.path = Some(path.to_path_buf());
+        Ok(project)
+    }
+
+    pub fn apply(&self, profile: &mut Profile) {
+        profile.config.merge(&self.config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap as Map;
+
+    use super::*;
+
+    #[test]
+    fn test_find_and_apply_project() {
+        let root = tempfile::tempdir().unwrap();
+        let nested_dir = root.path().join("payments/src");
+        std::fs::create_dir_all(&nested_dir).unwrap();
+        let project_path = root.path().join("payments").join(PROJECT_FILE_NAME);
+        std::fs::write(
+            &project_path,
+            r#"
+profile = "payments"
+proxy = "payments.example.com"
+label_filter = { team = "payments" }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(Project::find(&nested_dir), Some(project_path.clone()));
+        assert_eq!(Project::find(root.path()), None);
+
+        let project = Project::read(&project_path).unwrap();
+        assert_eq!(project.profile, Some("payments".to_owned()));
+
+        let mut profile = Profile {
+            name: "payments".to_owned(),
+            config: Config {
+                proxy: Some("teleport.example.com".to_owned()),
+                username: Some("dzefo".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        project.apply(&mut profile);
+
+        assert_eq!(
+            profile.config.proxy,
+            Some("payments.example.com".to_owned())
+        );
+        assert_eq!(profile.config.username, Some("dzefo".to_owned()));
+        assert_eq!(
+            profile.config.label_filter,
+            Some(Map::from([("team".to_owned(), "payments".to_owned())]))
+        );
+    }
+}
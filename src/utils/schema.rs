@@ -0,0 +1,132 @@
+use anyhow::{anyhow, Result};
+use toml::{value::Table, Value};
+
+/// The version of the profiles.toml layout, written by this version of beam
+pub const SCHEMA_VERSION: i64 = 1;
+
+type Migration = fn(&mut Table) -> Result<()>;
+
+/// Migrations upgrading the layout by one version each, starting from unversioned files (version 0)
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [migrate_v0_to_v1];
+
+/// Upgrades the parsed profiles to the current schema version.
+/// Returns the version the profiles had before, if they had to be migrated
+pub fn migrate(profiles: &mut Value) -> Result<Option<i64>> {
+    let table = profiles
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("Profiles must be a table"))?;
+    if table.is_empty() {
+        return Ok(None);
+    }
+
+    let version = match table.get("schema_version") {
+        Some(version) => version
+            .as_integer()
+            .ok_or_else(|| anyhow!("schema_version must be a number"))?,
+        None => 0,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Your profiles use schema version {}, but this version of beam only supports up to {}. Please update beam",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    for migration in &MIGRATIONS[version.max(0) as usize..] {
+        migration(table)?;
+    }
+    table.insert("schema_version".to_string(), Value::Integer(SCHEMA_VERSION));
+    Ok(Some(version))
+}
+
+/// Unversioned files already have the layout of version 1
+fn migrate_v0_to_v1(_profiles: &mut Table) -> Result<()> {
+    Ok(())
+}
+
+/// Lists the keys of `original`, which are missing in `known`, as dotted paths.
+/// `known` is expected to be the result of parsing `original` and serializing it again,
+/// so every key beam doesn't know about has been dropped from it
+pub fn unknown_keys(original: &Value, known: &Value) -> Vec<String> {
+    let mut keys = vec![];
+    collect_unknown_keys(original, known, "", &mut keys);
+    keys
+}
+
+fn collect_unknown_keys(original: &Value, known: &Value, prefix: &str, keys: &mut Vec<String>) {
+    let (Some(original), Some(known)) = (original.as_table(), known.as_table()) else {
+        return;
+    };
+    for (key, value) in original {
+        let path = if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match known.get(key) {
+            Some(known_value) => collect_unknown_keys(value, known_value, &path, keys),
+            None => keys.push(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let mut unversioned: Value = toml::from_str(
+            r#"
+[profile.test]
+default = true
+proxy = "teleport.example.com"
+"#,
+        )
+        .unwrap();
+        assert_eq!(migrate(&mut unversioned).unwrap(), Some(0));
+        assert_eq!(
+            unversioned.get("schema_version"),
+            Some(&Value::Integer(SCHEMA_VERSION))
+        );
+        assert_eq!(migrate(&mut unversioned).unwrap(), None);
+
+        let mut newer: Value = toml::from_str("schema_version = 99").unwrap();
+        assert!(migrate(&mut newer).is_err());
+
+        let mut empty: Value = toml::from_str("").unwrap();
+        assert_eq!(migrate(&mut empty).unwrap(), None);
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let original: Value = toml::from_str(
+            r#"
+schema_version = 1
+legacy = true
+
+[profile.test]
+default = true
+forwards = []
+"#,
+        )
+        .unwrap();
+        let known: Value = toml::from_str(
+            r#"
+schema_version = 1
+
+[profile.test]
+default = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            unknown_keys(&original, &known),
+            vec!["legacy".to_string(), "profile.test.forwards".to_string()]
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use anyhow::{Context, Result};
+use std::{fmt, path::PathBuf};
+
+use crate::cli::Beam;
+use crate::teleport::backend::{self, TshCommand};
+use crate::teleport::node::{Filter, Listing, Node};
+use crate::utils::{login, profile::Profile, profiles::Profiles, project::Project};
+
+const PROFILE_ENV: &str = "BEAM_PROFILE";
+const USER_ENV: &str = "BEAM_USER";
+const PROXY_ENV: &str = "BEAM_PROXY";
+const AUTH_ENV: &str = "BEAM_AUTH";
+const CACHE_TTL_ENV: &str = "BEAM_CACHE_TTL";
+const CLUSTER_ENV: &str = "BEAM_CLUSTER";
+pub const TSH_ENV: &str = "BEAM_TSH";
+
+pub const DEFAULT_CACHE_TTL: u64 = 60 * 60 * 24;
+
+/// Where an effective value was taken from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Flag,
+    /// Named as an argument of the subcommand, e.g. `beam profile show prod`
+    Argument,
+    Env(&'static str),
+    Matched,
+    Project(PathBuf),
+    Profile(String),
+    LoginRule(String),
+    Chosen,
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Flag => write!(f, "command line flag"),
+            Source::Argument => write!(f, "command line argument"),
+            Source::Env(name) => write!(f, "environment variable {}", name),
+            Source::Matched => write!(f, "matching host pattern or labels"),
+            Source::Project(path) => write!(f, "project file {}", path.display()),
+            Source::Profile(name) => write!(f, "profile {}", name),
+            Source::LoginRule(rule) => write!(f, "login rule {}", rule),
+            Source::Chosen => write!(f, "interactive choice"),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// The effective values for a command. Flags win over environment variables,
+/// environment variables over the project file and the project file over the profile.
+/// Resolving the settings also selects the tsh of the profile for its proxy
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub profile: Setting<Profile>,
+    pub proxy: Option<Setting<String>>,
+    pub user: Setting<String>,
+    pub auth: Option<Setting<String>>,
+    pub cluster: Option<Setting<String>>,
+    pub cache_ttl: Setting<u64>,
+}
+
+impl Settings {
+    /// Resolves the settings, without knowing the host to connect to yet
+    pub fn get(beam: &Beam) -> Result<Settings> {
+        Settings::resolve(beam, None)
+    }
+
+    /// Resolves the settings for the given node, using a profile matching the node if there is one
+    pub fn get_for_node(beam: &Beam, node: &Node) -> Result<Settings> {
+        Settings::resolve(beam, Some(node))
+    }
+
+    fn resolve(beam: &Beam, node: Option<&Node>) -> Result<Settings> {
+        let project = Project::get()?;
+        let matched_profile = match node {
+            Some(node) => Profiles::get_matching(node, Profiles::get()?)?,
+            None => None,
+        };
+
+        let profile = if let Some(name) = &beam.profile {
+            Setting {
+                value: Profile::get(name)?,
+                source: Source::Flag,
+            }
+        } else if let Some(name) = env(PROFILE_ENV) {
+            Setting {
+                value: Profile::get(&name)?,
+                source: Source::Env(PROFILE_ENV),
+            }
+        } else if let Some(profile) = matched_profile {
+            Setting {
+                value: profile,
+                source: Source::Matched,
+            }
+        } else if let Some(name) = &project.profile {
+            Setting {
+                value: Profile::get(name).with_context(|| {
+                    format!(
+                        "Profile {} from {} does not exist",
+                        name,
+                        project.path.as_ref().unwrap().display()
+                    )
+                })?,
+                source: Source::Project(project.path.clone().unwrap()),
+            }
+        } else if Profiles::get()?.is_empty() && (beam.proxy.is_some() || env(PROXY_ENV).is_some())
+        {
+            // Running without any profiles, e.g. in CI
+            Setting {
+                value: Profile::new("default".to_string(), true, None, None),
+                source: Source::Default,
+            }
+        } else {
+            Setting {
+                value: Profiles::resolve_default(beam.is_interactive())?,
+                source: Source::Default,
+            }
+        };
+
+        Settings::from_profile(beam, &project, profile)
+    }
+
+    pub fn from_profile(
+        beam: &Beam,
+        project: &Project,
+        mut profile: Setting<Profile>,
+    ) -> Result<Settings> {
+        let project_source = project.path.clone().map(Source::Project);
+        let profile_source = Some(Source::Profile(profile.value.name.clone()));
+        let config = &profile.value.config;
+
+        let proxy = first_of(vec![
+            (beam.proxy.clone(), Some(Source::Flag)),
+            (env(PROXY_ENV), Some(Source::Env(PROXY_ENV))),
+            (project.config.proxy.clone(), project_source.clone()),
+            (config.proxy.clone(), profile_source.clone()),
+        ]);
+
+        let user = first_of(vec![
+            (beam.user.clone(), Some(Source::Flag)),
+            (env(USER_ENV), Some(Source::Env(USER_ENV))),
+            (project.config.username.clone(), project_source.clone()),
+            (config.username.clone(), profile_source.clone()),
+            (Some(whoami::username()), Some(Source::Default)),
+        ])
+        .unwrap();
+
+        let auth = first_of(vec![
+            (beam.auth.clone(), Some(Source::Flag)),
+            (env(AUTH_ENV), Some(Source::Env(AUTH_ENV))),
+            (project.config.auth.clone(), project_source.clone()),
+            (config.auth.clone(), profile_source.clone()),
+        ]);
+
+        let cluster = first_of(vec![
+            (beam.cluster.clone(), Some(Source::Flag)),
+            (env(CLUSTER_ENV), Some(Source::Env(CLUSTER_ENV))),
+            (project.config.cluster.clone(), project_source.clone()),
+            (config.cluster.clone(), profile_source.clone()),
+        ]);
+
+        let env_cache_ttl = match env(CACHE_TTL_ENV) {
+            Some(ttl) => Some(
+                ttl.parse::<u64>()
+                    .with_context(|| format!("{} must be a number of seconds", CACHE_TTL_ENV))?,
+            ),
+            None => None,
+        };
+        let cache_ttl = first_of(vec![
+            (env_cache_ttl, Some(Source::Env(CACHE_TTL_ENV))),
+            (project.config.cache_ttl, project_source),
+            (config.cache_ttl, profile_source),
+            (Some(DEFAULT_CACHE_TTL), Some(Source::Default)),
+        ])
+        .unwrap();
+
+        project.apply(&mut profile.value);
+        profile.value.config.merge(&beam.filter_overrides());
+        backend::set_tsh(
+            proxy.as_ref().map(|proxy| proxy.value.as_str()),
+            TshCommand::for_profile(&profile.value),
+        );
+
+        Ok(Settings {
+            profile,
+            proxy,
+            user,
+            auth,
+            cluster,
+            cache_ttl,
+        })
+    }
+
+    pub fn proxy(&self) -> Result<&String> {
+        self.proxy.as_ref().map(|proxy| &proxy.value).context(
+            "No proxy configured to login with. Please use --proxy or configure it using beam configure",
+        )
+    }
+
+    /// The login for the node. An explicitly given user wins over the login rules of the profile,
+    /// which win over the username template
+    pub fn login(&self, node: &Node) -> Result<Setting<String>> {
+        if let Source::Flag | Source::Env(_) = self.user.source {
+            return Ok(self.user.clone());
+        }
+        let profile = &self.profile.value;
+        for (index, rule) in profile.config.login_rules.iter().flatten().enumerate() {
+            if rule.matches(node)? {
+                return Ok(Setting {
+                    value: login::render(&rule.login, node)?,
+                    source: Source::LoginRule(format!(
+                        "login_rules[{}] ({}) of profile {}",
+                        index,
+                        rule.describe(),
+                        profile.name
+                    )),
+                });
+            }
+        }
+        Ok(Setting {
+            value: login::render(&self.user.value, node)?,
+            source: self.user.source.clone(),
+        })
+    }
+
+    /// The user to log in to teleport with. It is the username template filled in without a node.
+    /// Without a user, e.g. for a template using `{hostname}`, tsh falls back to the local username
+    pub fn teleport_user(&self) -> Option<String> {
+        login::render_user(&self.user.value)
+    }
+
+    pub fn auth(&self) -> Option<&String> {
+        self.auth.as_ref().map(|auth| &auth.value)
+    }
+
+    pub fn cluster(&self) -> Option<&str> {
+        self.cluster.as_ref().map(|cluster| cluster.value.as_str())
+    }
+
+    /// What to list the nodes of the proxy for and how
+    pub fn listing(&self) -> Listing {
+        Listing {
+            cluster: self.cluster().map(str::to_string),
+            filter: Filter::for_config(&self.profile.value.config),
+        }
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn first_of<T>(candidates: Vec<(Option<T>, Option<Source>)>) -> Option<Setting<T>> {
+    candidates.into_iter().find_map(|(value, source)| {
+        Some(Setting {
+            value: value?,
+            source: source?,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::utils::config::Config;
+
+    #[test]
+    fn test_from_profile_precedence() {
+        let beam = Beam::parse_from(["beam", "--user", "flag-user"]);
+        let project = Project {
+            path: Some(PathBuf::from("/work/payments/.beam.toml")),
+            profile: None,
+            config: Config {
+                proxy: Some("payments.example.com".to_owned()),
+                ..Default::default()
+            },
+        };
+        let profile = Profile {
+            name: "test".to_owned(),
+            config: Config {
+                proxy: Some("teleport.example.com".to_owned()),
+                username: Some("profile-user".to_owned()),
+                auth: Some("sso".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let settings = Settings::from_profile(
+            &beam,
+            &project,
+            Setting {
+                value: profile,
+                source: Source::Flag,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(settings.user.value, "flag-user");
+        assert_eq!(settings.user.source, Source::Flag);
+        assert_eq!(settings.proxy().unwrap(), "payments.example.com");
+        assert_eq!(
+            settings.proxy.unwrap().source,
+            Source::Project(PathBuf::from("/work/payments/.beam.toml"))
+        );
+        assert_eq!(
+            settings.auth.unwrap().source,
+            Source::Profile("test".to_owned())
+        );
+        assert_eq!(
+            settings.profile.value.config.proxy,
+            Some("payments.example.com".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_login() {
+        let profile: Profile = toml::from_str(
+            r#"
+default = true
+username = "{user}"
+
+[[login_rules]]
+label_selector = { os = "ubuntu" }
+login = "ubuntu"
+
+[[login_rules]]
+host_pattern = "^db-"
+login = "ec2-user"
+"#,
+        )
+        .unwrap();
+        let settings = Settings::from_profile(
+            &Beam::parse_from(["beam"]),
+            &Project::default(),
+            Setting {
+                value: profile,
+                source: Source::Default,
+            },
+        )
+        .unwrap();
+
+        let ubuntu = Node::new(
+            "db-1",
+            HashMap::from([("os".to_owned(), "ubuntu".to_owned())]),
+        );
+        let login = settings.login(&ubuntu).unwrap();
+        assert_eq!(login.value, "ubuntu");
+        assert!(matches!(login.source, Source::LoginRule(_)));
+
+        let amazon = Node::new("db-2", HashMap::new());
+        assert_eq!(settings.login(&amazon).unwrap().value, "ec2-user");
+
+        let other = Node::new("web-1", HashMap::new());
+        assert_eq!(settings.login(&other).unwrap().value, whoami::username());
+
+        let settings = Settings::from_profile(
+            &Beam::parse_from(["beam", "--user", "root"]),
+            &Project::default(),
+            settings.profile,
+        )
+        .unwrap();
+        assert_eq!(settings.login(&ubuntu).unwrap().value, "root");
+    }
+}
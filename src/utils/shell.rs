@@ -0,0 +1,33 @@
+/// Quotes the argument for POSIX shells. Arguments of only safe characters are kept as they are
+pub fn quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// The command line for the arguments, which a shell splits into the same arguments again
+pub fn join<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join(&["tsh", "--proxy=teleport.example.com", "ec2-user@db-1"]),
+            "tsh --proxy=teleport.example.com ec2-user@db-1"
+        );
+        assert_eq!(
+            join(&["/opt/my tsh/tsh", "", "$HOME", "it's"]),
+            r"'/opt/my tsh/tsh' '' '$HOME' 'it'\''s'"
+        );
+    }
+}
@@ -2,14 +2,22 @@ use anyhow::Result;
 use std::io::Cursor;
 
 use skim::{
-    prelude::{SkimItemReader, SkimOptionsBuilder},
+    prelude::{Event, SkimItemReader, SkimOptionsBuilder},
     Skim,
 };
 
-pub fn skim(items: String) -> Result<Option<String>> {
+/// The selected item and the key it was accepted with, if it was one of the expected keys
+pub struct Selection {
+    pub item: String,
+    pub key: Option<String>,
+}
+
+pub fn skim(items: String, expect: &[&str]) -> Result<Option<Selection>> {
+    let expect = expect.join(",");
     let options = SkimOptionsBuilder::default()
         .height(Some("100%"))
         .multi(true)
+        .expect((!expect.is_empty()).then_some(expect))
         .build()
         .unwrap();
 
@@ -19,9 +27,14 @@ pub fn skim(items: String) -> Result<Option<String>> {
     let selected_item = Skim::run_with(&options, Some(items))
         .map(|out| {
             if !out.is_abort {
-                out.selected_items
-                    .first()
-                    .map(|item| item.text().to_string())
+                let key = match out.final_event {
+                    Event::EvActAccept(key) => key,
+                    _ => None,
+                };
+                out.selected_items.first().map(|item| Selection {
+                    item: item.text().to_string(),
+                    key,
+                })
             } else {
                 None
             }
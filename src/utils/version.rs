@@ -10,7 +10,7 @@ pub async fn get_latest_release(url: &str) -> Result<Version> {
         .url()
         .path_segments()
         .unwrap()
-        .last()
+        .next_back()
         .ok_or_else(|| anyhow::anyhow!("Could not parse version string"))?;
 
     Version::parse(&version_string[1..]).context("Could not parse version string")
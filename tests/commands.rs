@@ -0,0 +1,731 @@
+use std::{fs, path::PathBuf, sync::Arc};
+
+use beamcli::cli::Beam;
+use beamcli::teleport::{backend, fake::FakeBackend};
+use beamcli::utils::error::ConfigError;
+use beamcli::utils::paths;
+use beamcli::utils::{profile::Profile, profiles::Profiles};
+use clap::Parser;
+use lazy_static::lazy_static;
+use semver::Version;
+use tempfile::TempDir;
+
+const PROXY: &str = "teleport.example.com";
+
+const PROFILES: &str = r#"schema_version = 1
+
+[profile.prod]
+default = true
+proxy = "teleport.example.com"
+username = "dzefo"
+
+[[profile.prod.login_rules]]
+host_pattern = "^db-"
+login = "ec2-user"
+"#;
+
+lazy_static! {
+    // The config directory and the backend are global, so the tests must not run concurrently
+    static ref LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn setup(backend: FakeBackend) -> (TempDir, Arc<FakeBackend>) {
+    setup_with_profiles(PROFILES, backend)
+}
+
+/// Points beam at a fresh config directory with the profiles and at the backend
+fn setup_with_profiles(profiles: &str, backend: FakeBackend) -> (TempDir, Arc<FakeBackend>) {
+    let config_dir = tempfile::tempdir().unwrap();
+    fs::write(config_dir.path().join("profiles.toml"), profiles).unwrap();
+    paths::set_config_dir(config_dir.path().to_path_buf());
+
+    let backend = Arc::new(
+        backend
+            .with_nodes_fixture(PROXY, fixture("nodes.json"))
+            .unwrap(),
+    );
+    backend::set_backend(backend.clone());
+    (config_dir, backend)
+}
+
+async fn beam(args: &[&str]) -> anyhow::Result<()> {
+    Beam::parse_from([&["beam"], args].concat())
+        .execute_command()
+        .await
+}
+
+#[tokio::test]
+async fn test_connect_logs_in_and_uses_login_rule() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) =
+        setup(FakeBackend::new().with_allowed_logins(PROXY, &["dzefo", "ec2-user"]));
+
+    beam(&["connect", "db-1"]).await.unwrap();
+
+    assert_eq!(
+        fake.calls(),
+        vec![
+            "status",
+            "login --proxy=teleport.example.com --user=dzefo",
+            "ls --proxy=teleport.example.com -f json",
+            "status",
+            "ssh --proxy=teleport.example.com ec2-user@db-1",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_connect_refuses_disallowed_login() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    assert!(beam(&["--user", "root", "connect", "web-1"]).await.is_err());
+    eprintln!("{:?}", fake.calls());
+    assert!(!fake.calls().iter().any(|call| call.starts_with("ssh")));
+}
+
+#[tokio::test]
+async fn test_connect_switches_to_valid_login() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_login("other.example.com", &["root"]),
+    );
+
+    beam(&["connect", "web-1"]).await.unwrap();
+
+    let calls = fake.calls();
+    assert_eq!(calls[1], "login --proxy=teleport.example.com");
+    assert_eq!(
+        calls.last().unwrap(),
+        "ssh --proxy=teleport.example.com dzefo@web-1"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_unknown_host() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    let err = beam(&["connect", "web-9"]).await.unwrap_err();
+
+    assert_eq!(err.to_string(), "Host not found in teleport");
+    eprintln!("{:?}", fake.calls());
+    assert!(!fake.calls().iter().any(|call| call.starts_with("ssh")));
+}
+
+#[tokio::test]
+async fn test_connect_failed_login() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_expired_login(PROXY).failing_login());
+
+    assert!(beam(&["connect", "web-1"]).await.is_err());
+    eprintln!("{:?}", fake.calls());
+    assert!(!fake.calls().iter().any(|call| call.starts_with("ls")));
+}
+
+#[tokio::test]
+async fn test_list() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    beam(&["list", "--format", "json"]).await.unwrap();
+
+    assert_eq!(
+        fake.calls().last().unwrap(),
+        "ls --proxy=teleport.example.com -f json"
+    );
+}
+
+#[tokio::test]
+async fn test_login() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new());
+
+    beam(&["login"]).await.unwrap();
+    beam(&["login"]).await.unwrap();
+
+    // The second login finds the active login and doesn't log in again
+    assert_eq!(
+        fake.calls(),
+        vec![
+            "status",
+            "login --proxy=teleport.example.com --user=dzefo",
+            "status"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_default_non_interactive() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    assert!(beam(&["--non-interactive"]).await.is_err());
+    assert!(fake.calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_profile_remove_non_interactive() {
+    let _lock = LOCK.lock().await;
+    let (config_dir, _fake) = setup(FakeBackend::new());
+
+    let err = beam(&["--non-interactive", "profile", "remove"])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        err.downcast_ref::<ConfigError>(),
+        Some(ConfigError::InteractionRequired(_))
+    ));
+    assert_eq!(
+        fs::read_to_string(config_dir.path().join("profiles.toml")).unwrap(),
+        PROFILES
+    );
+}
+
+#[tokio::test]
+async fn test_connect_with_tsh_args() {
+    let _lock = LOCK.lock().await;
+    let profiles = PROFILES.replace(
+        "username = \"dzefo\"\n",
+        "username = \"dzefo\"\ntsh_path = \"/opt/teleport-9/tsh\"\ntsh_args = [\"--insecure\"]\n",
+    );
+    let (_config_dir, fake) =
+        setup_with_profiles(&profiles, FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    beam(&["connect", "web-1"]).await.unwrap();
+
+    // The global arguments are passed by the backend and not part of the ssh arguments
+    assert_eq!(
+        fake.calls().last().unwrap(),
+        "ssh --proxy=teleport.example.com dzefo@web-1"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_with_old_tsh() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_version(Version::new(6, 2, 1)),
+    );
+
+    let err = beam(&["--clear-cache", "connect", "web-1"])
+        .await
+        .unwrap_err();
+
+    assert!(err
+        .to_string()
+        .starts_with("Listing nodes requires tsh 7.0.0 or newer"));
+    eprintln!("{:?}", fake.calls());
+    assert!(!fake.calls().iter().any(|call| call.starts_with("ls")));
+}
+
+#[tokio::test]
+async fn test_list_with_filters() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    beam(&[
+        "list",
+        "--query",
+        r#"labels["os"] == "ubuntu""#,
+        "--search",
+        "web",
+        "--labels",
+        "env=prod",
+    ])
+    .await
+    .unwrap();
+
+    assert_eq!(
+        fake.calls().last().unwrap(),
+        r#"ls --proxy=teleport.example.com -f text --query=labels["os"] == "ubuntu" --search=web env=prod"#
+    );
+}
+
+#[tokio::test]
+async fn test_filters_need_newer_tsh() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_version(Version::new(8, 2, 0)),
+    );
+
+    assert!(beam(&["list", "--search", "web"]).await.is_err());
+    beam(&["list", "--labels", "env=prod"]).await.unwrap();
+    assert_eq!(
+        fake.calls().last().unwrap(),
+        "ls --proxy=teleport.example.com -f text env=prod"
+    );
+}
+
+#[tokio::test]
+async fn test_connect_caches_filtered_nodes_separately() {
+    let _lock = LOCK.lock().await;
+    let profiles = PROFILES.replace(
+        "username = \"dzefo\"\n",
+        "username = \"dzefo\"\nlabel_filter = { env = \"prod\" }\n",
+    );
+    let (config_dir, fake) =
+        setup_with_profiles(&profiles, FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    beam(&["connect", "web-1"]).await.unwrap();
+    beam(&["--search", "web", "connect", "web-1"])
+        .await
+        .unwrap();
+    beam(&["connect", "web-1"]).await.unwrap();
+
+    let ls_calls: Vec<String> = fake
+        .calls()
+        .into_iter()
+        .filter(|call| call.starts_with("ls"))
+        .collect();
+    assert_eq!(
+        ls_calls,
+        vec![
+            "ls --proxy=teleport.example.com -f json env=prod",
+            "ls --proxy=teleport.example.com -f json --search=web env=prod",
+        ]
+    );
+    let cache_files = fs::read_dir(config_dir.path().join("cache"))
+        .unwrap()
+        .count();
+    assert_eq!(cache_files, 2);
+}
+
+#[tokio::test]
+async fn test_db_connect_with_matching_profile() {
+    let _lock = LOCK.lock().await;
+    let profiles = format!(
+        r#"{}
+[profile.billing]
+default = false
+host_pattern = "^billing$"
+proxy = "teleport.example.com"
+db_user = "readonly"
+db_name = "invoices"
+"#,
+        PROFILES
+    );
+    let databases = fs::read_to_string(fixture("databases.json")).unwrap();
+    let (_config_dir, fake) = setup_with_profiles(
+        &profiles,
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_resources("db", PROXY, &databases),
+    );
+
+    beam(&["db", "billing"]).await.unwrap();
+    beam(&["db", "billing", "--db-user", "admin", "--port", "5432"])
+        .await
+        .unwrap();
+
+    let calls = fake.calls();
+    assert!(calls.contains(&"db ls --proxy=teleport.example.com --format=json".to_owned()));
+    assert!(calls.contains(
+        &"db connect --proxy=teleport.example.com --db-user=readonly --db-name=invoices billing"
+            .to_owned()
+    ));
+    assert_eq!(
+        calls.last().unwrap(),
+        "proxy db --tunnel --port=5432 --proxy=teleport.example.com --db-user=admin --db-name=invoices billing"
+    );
+    // The second run uses the cached databases
+    assert_eq!(
+        calls
+            .iter()
+            .filter(|call| call.starts_with("db ls"))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_db_unknown_and_dry_run() {
+    let _lock = LOCK.lock().await;
+    let databases = fs::read_to_string(fixture("databases.json")).unwrap();
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_resources("db", PROXY, &databases),
+    );
+
+    assert!(beam(&["db", "orders"]).await.is_err());
+    beam(&["--tsh", "db", "analytics"]).await.unwrap();
+
+    eprintln!("{:?}", fake.calls());
+    assert!(!fake.calls().iter().any(|call| call.starts_with("db login")));
+}
+
+#[tokio::test]
+async fn test_db_connect_fails() {
+    let _lock = LOCK.lock().await;
+    let databases = fs::read_to_string(fixture("databases.json")).unwrap();
+    let (_config_dir, _fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_resources("db", PROXY, &databases)
+            .failing_run("db connect"),
+    );
+
+    let err = beam(&["db", "analytics"]).await.unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "tsh db connect for database analytics failed"
+    );
+}
+
+#[tokio::test]
+async fn test_kube_login() {
+    let _lock = LOCK.lock().await;
+    let kube_clusters = fs::read_to_string(fixture("kube_clusters.json")).unwrap();
+    let (config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_resources("kube", PROXY, &kube_clusters),
+    );
+
+    beam(&["kube", "prod-eu"]).await.unwrap();
+    // Printing the commands for a namespace neither logs in nor runs kubectl
+    beam(&["--tsh", "kube", "staging", "--namespace", "payments"])
+        .await
+        .unwrap();
+
+    let calls = fake.calls();
+    assert!(calls.contains(&"kube login --proxy=teleport.example.com prod-eu".to_owned()));
+    assert_eq!(
+        calls
+            .iter()
+            .filter(|call| call.starts_with("kube login"))
+            .count(),
+        1
+    );
+    assert_eq!(
+        calls
+            .iter()
+            .filter(|call| call.starts_with("kube ls"))
+            .count(),
+        1
+    );
+    assert!(config_dir
+        .path()
+        .join("cache/kube/teleport.example.com.json")
+        .is_file());
+}
+
+#[tokio::test]
+async fn test_app_login() {
+    let _lock = LOCK.lock().await;
+    let apps = fs::read_to_string(fixture("apps.json")).unwrap();
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_resources("apps", PROXY, &apps),
+    );
+
+    beam(&["app", "grafana"]).await.unwrap();
+    beam(&["--labels", "env=prod", "app", "grafana", "--port", "8080"])
+        .await
+        .unwrap();
+
+    let calls = fake.calls();
+    assert!(calls.contains(&"apps login --proxy=teleport.example.com grafana".to_owned()));
+    assert!(calls.contains(&"apps config --proxy=teleport.example.com grafana".to_owned()));
+    // The label filter is passed to teleport and the apps are cached for each filter
+    assert!(
+        calls.contains(&"apps ls --proxy=teleport.example.com --format=json env=prod".to_owned())
+    );
+    assert_eq!(
+        calls.last().unwrap(),
+        "proxy app --port=8080 --proxy=teleport.example.com grafana"
+    );
+}
+
+#[tokio::test]
+async fn test_app_proxy_fails() {
+    let _lock = LOCK.lock().await;
+    let apps = fs::read_to_string(fixture("apps.json")).unwrap();
+    let (_config_dir, _fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_resources("apps", PROXY, &apps)
+            .failing_run("proxy app"),
+    );
+
+    let err = beam(&["app", "grafana", "--port", "8080"])
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.to_string(), "tsh proxy app for app grafana failed");
+}
+
+#[tokio::test]
+async fn test_ssh_config() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    beam(&["ssh-config", "env=prod"]).await.unwrap();
+    beam(&["ssh-config", "db-.*"]).await.unwrap();
+    let err = beam(&["ssh-config", "web-9"]).await.unwrap_err();
+
+    assert_eq!(err.to_string(), "No host in teleport matches the selector");
+    assert!(!fake
+        .calls()
+        .iter()
+        .any(|call| call.starts_with("ssh") || call.starts_with("login")));
+}
+
+#[tokio::test]
+async fn test_dry_run_formats() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(FakeBackend::new().with_allowed_logins(PROXY, &["dzefo"]));
+
+    // Without a login, only the login beam would run is printed
+    beam(&["--tsh-format", "env", "login"]).await.unwrap();
+    beam(&["--tsh", "logout"]).await.unwrap();
+    // Commands, which don't need to list anything first, don't log in either
+    beam(&["--tsh-format", "json", "list"]).await.unwrap();
+    beam(&["--tsh", "clusters"]).await.unwrap();
+    beam(&["--tsh", "request", "ls"]).await.unwrap();
+    beam(&["--tsh", "request", "dba"]).await.unwrap();
+    beam(&["--tsh-format", "json", "doctor"]).await.unwrap();
+    assert!(beam(&["--tsh-format", "openssh", "list"]).await.is_err());
+    assert!(fake.calls().iter().all(|call| call == "status"));
+
+    // Connecting logs in to list the nodes, but doesn't run ssh
+    beam(&["--tsh-format", "openssh", "connect", "web-1"])
+        .await
+        .unwrap();
+    assert!(!fake.calls().iter().any(|call| call.starts_with("ssh")));
+    assert_eq!(
+        fake.calls()
+            .iter()
+            .filter(|call| call.starts_with("ls"))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_clusters() {
+    let _lock = LOCK.lock().await;
+    let clusters = r#"[{"cluster_name": "teleport.example.com", "status": "online", "cluster_type": "root", "selected": true}]"#;
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_output("clusters", clusters),
+    );
+
+    beam(&["clusters"]).await.unwrap();
+    assert_eq!(
+        fake.calls().last().unwrap(),
+        "clusters --proxy=teleport.example.com --format=json"
+    );
+
+    let (_config_dir, _fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .failing_output("clusters", "ERROR: access denied"),
+    );
+    let err = beam(&["clusters"]).await.unwrap_err();
+    assert!(err.to_string().contains("access denied"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_request_logs_in_once_approved() {
+    let _lock = LOCK.lock().await;
+    let request_id = "0d5a4a3c-9e2a-4a0e-9d5b-3f1c2b7a8e61";
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_output("request create", &format!("Request ID: {}\n", request_id))
+            .with_output(
+                "request show",
+                &format!(
+                    r#"{{"metadata": {{"name": "{}"}}, "spec": {{"roles": ["dba"], "state": "APPROVED"}}}}"#,
+                    request_id
+                ),
+            ),
+    );
+
+    beam(&["request", "dba", "--reason", "INC-42"])
+        .await
+        .unwrap();
+
+    let calls = fake.calls();
+    assert_eq!(
+        calls[calls.len() - 3..],
+        [
+            "request create --proxy=teleport.example.com --roles=dba --nowait --reason=INC-42"
+                .to_owned(),
+            format!(
+                "request show --proxy=teleport.example.com {} --format=json",
+                request_id
+            ),
+            format!(
+                "login --proxy=teleport.example.com --request-id={}",
+                request_id
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_request_show_fails() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, _fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_output("request create", "Request ID: a\n")
+            .failing_output("request show", "ERROR: connection refused"),
+    );
+
+    let err = beam(&["request", "dba", "--reason", "INC-42"])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Could not show access request a: ERROR: connection refused"
+    );
+}
+
+#[tokio::test]
+async fn test_request_denied_or_expired() {
+    let _lock = LOCK.lock().await;
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_output("request create", "Request ID: a\n")
+            .with_output(
+                "request show",
+                r#"{"metadata": {"name": "a"}, "spec": {"roles": ["dba"], "state": "DENIED"}}"#,
+            ),
+    );
+
+    let err = beam(&["request", "dba", "--reason", "INC-42"])
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Access request a was denied");
+    assert!(!fake
+        .calls()
+        .iter()
+        .any(|call| call.contains("--request-id")));
+
+    let (_config_dir, _fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_output("request create", "Request ID: a\n")
+            .failing_output("request show", "ERROR: access request \"a\" not found"),
+    );
+    let err = beam(&["request", "dba", "--reason", "INC-42"])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Access request a expired before it was reviewed"
+    );
+}
+
+#[tokio::test]
+async fn test_request_for_host_needs_role() {
+    let _lock = LOCK.lock().await;
+    let profiles = format!(
+        "{}\n[profile.db]\ndefault = false\nhost_pattern = \"^db-\"\nproxy = \"teleport.example.com\"\n",
+        PROFILES
+    );
+    let (_config_dir, fake) =
+        setup_with_profiles(&profiles, FakeBackend::new().with_login(PROXY, &["dzefo"]));
+
+    let err = beam(&["request", "db-1", "--reason", "INC-42"])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "No request_role for host db-1 in profile db, use --role"
+    );
+    eprintln!("{:?}", fake.calls());
+    assert!(!fake.calls().iter().any(|call| call.starts_with("request")));
+}
+
+#[tokio::test]
+async fn test_request_ls() {
+    let _lock = LOCK.lock().await;
+    let requests =
+        r#"[{"metadata": {"name": "a"}, "spec": {"roles": ["dba"], "state": "PENDING"}}]"#;
+    let (_config_dir, fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .with_output("request ls", requests),
+    );
+
+    beam(&["request", "ls"]).await.unwrap();
+    assert_eq!(
+        fake.calls().last().unwrap(),
+        "request ls --proxy=teleport.example.com --format=json"
+    );
+
+    let (_config_dir, _fake) = setup(
+        FakeBackend::new()
+            .with_login(PROXY, &["dzefo"])
+            .failing_output("request ls", "ERROR: access denied"),
+    );
+    assert!(beam(&["request", "ls"]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_newer_schema_is_not_overwritten() {
+    let _lock = LOCK.lock().await;
+    let profiles = PROFILES.replace("schema_version = 1", "schema_version = 99");
+    let (config_dir, _fake) = setup_with_profiles(&profiles, FakeBackend::new());
+
+    let err = beam(&["profile", "show"]).await.unwrap_err();
+    assert!(err.to_string().contains("update beam"), "{}", err);
+    assert_eq!(
+        beam(&["doctor"]).await.unwrap_err().to_string(),
+        "Found 1 problem(s)"
+    );
+
+    let profile = Profile {
+        name: "staging".to_owned(),
+        ..Default::default()
+    };
+    assert!(Profiles::write(profile).is_err());
+    assert_eq!(
+        fs::read_to_string(config_dir.path().join("profiles.toml")).unwrap(),
+        profiles
+    );
+}
+
+#[tokio::test]
+async fn test_login_with_templated_username() {
+    let _lock = LOCK.lock().await;
+    let profiles = PROFILES.replace("username = \"dzefo\"", "username = \"{user}\"");
+    let (_config_dir, fake) = setup_with_profiles(&profiles, FakeBackend::new());
+
+    beam(&["login"]).await.unwrap();
+
+    assert_eq!(
+        fake.calls()[1],
+        format!("login --proxy={} --user={}", PROXY, whoami::username())
+    );
+
+    // A template depending on the host leaves the teleport user to tsh
+    let profiles = PROFILES.replace("username = \"dzefo\"", "username = \"{hostname}\"");
+    let (_config_dir, fake) = setup_with_profiles(&profiles, FakeBackend::new());
+
+    beam(&["login"]).await.unwrap();
+
+    assert_eq!(fake.calls()[1], format!("login --proxy={}", PROXY));
+}